@@ -0,0 +1,36 @@
+// Each benchmark binary uses only some of these helpers.
+#![allow(dead_code)]
+
+use nalgebra::DVector;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// Synthetic data for the benchmarks, generated from a fixed seed so that runs are
+// reproducible and need no dataset on disk.
+
+pub fn random_features(feature_dim: usize, seed: u64) -> DVector<f32> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    DVector::from_fn(feature_dim, |_, _| rng.gen_range(-1.0..1.0))
+}
+
+pub fn random_inputs(input_dim: usize, seed: u64) -> Vec<f32> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    (0..input_dim).map(|_| rng.gen_range(0.01..0.99)).collect()
+}
+
+// Examples labelled by a random linear separator, so that the model has something to learn.
+pub fn linear_examples(
+    feature_dim: usize,
+    num_examples: usize,
+    seed: u64,
+) -> Vec<(DVector<f32>, i32)> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let separator = DVector::from_fn(feature_dim, |_, _| rng.gen_range(-1.0..1.0));
+    (0..num_examples)
+        .map(|_| {
+            let features = DVector::from_fn(feature_dim, |_, _| rng.gen_range(-1.0..1.0));
+            let target = (features.dot(&separator) > 0.0) as i32;
+            (features, target)
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gln::model::context_func::{ContextFunction, HalfSpaceContext, LayerContextEvaluator};
+use gln::model::gate::{initialize_balanced_weights, Gate};
+use gln::model::gln_model::GLN;
+use gln::model::layer::Layer;
+use gln::model::neuron::Neuron;
+use nalgebra::{DMatrix, DVector};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+mod common;
+
+const FEATURE_DIMS: [usize; 3] = [4, 16, 64];
+const CONTEXT_DIMS: [usize; 3] = [2, 4, 8];
+const LAYER_WIDTHS: [usize; 3] = [4, 16, 64];
+
+fn bench_indicator_func(c: &mut Criterion) {
+    let mut group = c.benchmark_group("half_space_indicator_func");
+    for feature_dim in FEATURE_DIMS {
+        for context_dim in CONTEXT_DIMS {
+            let context = HalfSpaceContext::<f32>::from_rng(
+                context_dim,
+                feature_dim,
+                &mut ChaCha8Rng::seed_from_u64(0),
+            );
+            let features = common::random_features(feature_dim, 1);
+            group.bench_with_input(
+                BenchmarkId::new(format!("feature_dim={}", feature_dim), context_dim),
+                &features,
+                |b, features| b.iter(|| black_box(context.indicator_func(features.as_slice()))),
+            );
+        }
+    }
+    group.finish();
+}
+
+// Per-neuron context evaluation against one stacked product for the whole layer.
+fn bench_layer_context_indices(c: &mut Criterion) {
+    let mut group = c.benchmark_group("layer_context_indices");
+    for width in LAYER_WIDTHS {
+        for feature_dim in FEATURE_DIMS {
+            let mut rng = ChaCha8Rng::seed_from_u64(0);
+            let contexts: Vec<HalfSpaceContext> = (0..width)
+                .map(|_| HalfSpaceContext::from_rng(4, feature_dim, &mut rng))
+                .collect();
+            let evaluator = LayerContextEvaluator::new(&contexts.iter().collect::<Vec<_>>());
+            let features = common::random_features(feature_dim, 1);
+            let label = format!("width={}", width);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("per_neuron/{}", label), feature_dim),
+                &features,
+                |b, features| {
+                    let mut context_indices = Vec::with_capacity(width);
+                    b.iter(|| {
+                        context_indices.clear();
+                        context_indices.extend(
+                            contexts
+                                .iter()
+                                .map(|context| context.context_index(features.as_slice())),
+                        );
+                        black_box(&context_indices);
+                    })
+                },
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("stacked/{}", label), feature_dim),
+                &features,
+                |b, features| {
+                    let mut projections = DVector::zeros(0);
+                    let mut context_indices = Vec::with_capacity(width);
+                    b.iter(|| {
+                        evaluator.context_indices_into(
+                            features,
+                            &mut projections,
+                            &mut context_indices,
+                        );
+                        black_box(&context_indices);
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_select_weights(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gate_select_weights");
+    for feature_dim in FEATURE_DIMS {
+        for context_dim in CONTEXT_DIMS {
+            let gate = Gate::<HalfSpaceContext>::new(
+                16,
+                context_dim,
+                feature_dim,
+                initialize_balanced_weights,
+            );
+            let features = common::random_features(feature_dim, 1);
+            group.bench_with_input(
+                BenchmarkId::new(format!("feature_dim={}", feature_dim), context_dim),
+                &features,
+                |b, features| b.iter(|| black_box(gate.select_weights(features))),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_update_weights(c: &mut Criterion) {
+    let mut group = c.benchmark_group("neuron_update_weights");
+    for input_dim in LAYER_WIDTHS {
+        for context_dim in CONTEXT_DIMS {
+            let mut neuron: Neuron<HalfSpaceContext> =
+                Neuron::with_half_space_context(input_dim, context_dim, 8, 0.01, 5.0, 1.0, 0.0);
+            let inputs = common::random_inputs(input_dim, 1);
+            group.bench_with_input(
+                BenchmarkId::new(format!("input_dim={}", input_dim), context_dim),
+                &inputs,
+                |b, inputs| b.iter(|| neuron.update_weights(inputs, 1, 0)),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_calculate_next_weight_matrix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("layer_calculate_next_weight_matrix");
+    for width in LAYER_WIDTHS {
+        for feature_dim in FEATURE_DIMS {
+            let layer: Layer =
+                Layer::with_neuron_num(width, width, 4, feature_dim, 0.01, 5.0, 1.0, 0.0);
+            let features = common::random_features(feature_dim, 1);
+            let previous = DMatrix::from_vec(width, 1, common::random_inputs(width, 2));
+            group.bench_with_input(
+                BenchmarkId::new(format!("width={}", width), feature_dim),
+                &features,
+                |b, features| {
+                    b.iter(|| black_box(layer.calculate_next_weight_matrix(features, &previous)))
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_gln_predict_fit(c: &mut Criterion) {
+    let num_examples = 128;
+    let mut group = c.benchmark_group("gln_predict_fit");
+    group.throughput(Throughput::Elements(num_examples as u64));
+    for width in LAYER_WIDTHS {
+        for feature_dim in FEATURE_DIMS {
+            let examples = common::linear_examples(feature_dim, num_examples, 0);
+            let mut model: GLN = GLN::new(
+                vec![width, width / 2, 1],
+                4,
+                feature_dim,
+                0.01,
+                5.0,
+                1.0,
+                0.0,
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("width={}", width), feature_dim),
+                &examples,
+                |b, examples| {
+                    b.iter(|| {
+                        for (features, target) in examples.iter() {
+                            black_box(model.predict_fit(features, *target).prediction);
+                        }
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_indicator_func,
+    bench_layer_context_indices,
+    bench_select_weights,
+    bench_update_weights,
+    bench_calculate_next_weight_matrix,
+    bench_gln_predict_fit
+);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gln::model::gln_model::GLN;
+
+mod common;
+
+// Compares the allocating `predict_fit` with `predict_fit_with_workspace` on small networks,
+// where building the per-call maps and matrices dominates.
+fn bench_predict_fit(c: &mut Criterion) {
+    let num_examples = 256;
+    let mut group = c.benchmark_group("predict_fit");
+    group.throughput(Throughput::Elements(num_examples as u64));
+
+    for (neuron_nums, feature_dim) in
+        vec![(vec![4, 1], 4), (vec![16, 8, 1], 8), (vec![32, 16, 1], 16)]
+    {
+        let examples = common::linear_examples(feature_dim, num_examples, 0);
+        let label = format!("{:?}x{}", neuron_nums, feature_dim);
+        let model: GLN = GLN::new(neuron_nums, 4, feature_dim, 0.01, 5.0, 1.0, 0.0).with_seed(0);
+
+        group.bench_with_input(
+            BenchmarkId::new("allocating", &label),
+            &examples,
+            |b, examples| {
+                let mut model = model.clone();
+                b.iter(|| {
+                    for (features, target) in examples.iter() {
+                        black_box(model.predict_fit(features, *target).prediction);
+                    }
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("workspace", &label),
+            &examples,
+            |b, examples| {
+                let mut model = model.clone();
+                let mut workspace = model.workspace();
+                b.iter(|| {
+                    for (features, target) in examples.iter() {
+                        black_box(model.predict_fit_with_workspace(
+                            features,
+                            *target,
+                            1.0,
+                            &mut workspace,
+                        ));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_predict_fit);
+criterion_main!(benches);
@@ -0,0 +1,155 @@
+use nalgebra::{convert, DVector};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Beta, Distribution};
+
+use crate::model::gln_model::GLN;
+use crate::utils::float::GLNFloat;
+
+pub enum ExplorationStrategy<T: GLNFloat = f32> {
+    // Adds `exploration / sqrt(pseudo_count + 1)` to the predicted reward probability.
+    Ucb { exploration: T },
+    // Samples from Beta(p * n + 1, (1 - p) * n + 1) where n is the pseudo count.
+    Thompson,
+}
+
+enum ArmModels<T: GLNFloat> {
+    PerArm(Vec<GLN<T>>),
+    // A single model whose features are the context followed by a one-hot arm indicator.
+    Shared(GLN<T>),
+}
+
+pub struct ArmScore<T: GLNFloat = f32> {
+    pub probability: T,
+    pub pseudo_count: T,
+    pub score: T,
+}
+
+pub struct ArmSelection<T: GLNFloat = f32> {
+    pub arm: usize,
+    pub scores: Vec<ArmScore<T>>,
+}
+
+// Gated linear contextual bandit. Reward probabilities come from the GLNs and the
+// exploration bonus from how often the selected contexts have been trained.
+pub struct GLCB<T: GLNFloat = f32> {
+    models: ArmModels<T>,
+    num_arms: usize,
+    strategy: ExplorationStrategy<T>,
+    rng: ChaCha8Rng,
+    last_context: Option<DVector<T>>,
+}
+
+impl<T: GLNFloat> GLCB<T> {
+    pub fn new(models: Vec<GLN<T>>, strategy: ExplorationStrategy<T>, seed: u64) -> Self {
+        let num_arms = models.len();
+        GLCB {
+            models: ArmModels::PerArm(models),
+            num_arms,
+            strategy,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            last_context: None,
+        }
+    }
+
+    // `model` takes the context followed by `num_arms` arm indicator features.
+    pub fn with_shared_model(
+        model: GLN<T>,
+        num_arms: usize,
+        strategy: ExplorationStrategy<T>,
+        seed: u64,
+    ) -> Self {
+        GLCB {
+            models: ArmModels::Shared(model),
+            num_arms,
+            strategy,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            last_context: None,
+        }
+    }
+
+    pub fn num_arms(&self) -> usize {
+        self.num_arms
+    }
+
+    pub fn select(&mut self, context: &DVector<T>) -> ArmSelection<T> {
+        let scores: Vec<ArmScore<T>> = (0..self.num_arms)
+            .map(|arm| self.score(arm, context))
+            .collect();
+
+        let mut arm = 0;
+        for (candidate, score) in scores.iter().enumerate() {
+            if score.score > scores[arm].score {
+                arm = candidate;
+            }
+        }
+
+        self.last_context = Some(context.clone());
+        ArmSelection { arm, scores }
+    }
+
+    // Trains the model of `arm` on the context passed to the last `select` call.
+    pub fn update(&mut self, arm: usize, reward: i32) {
+        let context = self
+            .last_context
+            .clone()
+            .expect("`select` has to be called before `update`.");
+        self.update_with_context(arm, &context, reward);
+    }
+
+    pub fn update_with_context(&mut self, arm: usize, context: &DVector<T>, reward: i32) {
+        match &mut self.models {
+            ArmModels::PerArm(models) => {
+                models[arm].predict_fit(context, reward);
+            }
+            ArmModels::Shared(model) => {
+                let features = Self::arm_features(context, arm, self.num_arms);
+                model.predict_fit(&features, reward);
+            }
+        }
+    }
+
+    fn score(&mut self, arm: usize, context: &DVector<T>) -> ArmScore<T> {
+        let (probability, pseudo_count) = match &self.models {
+            ArmModels::PerArm(models) => {
+                let prediction = models[arm].predict(context);
+                (
+                    prediction.probability,
+                    models[arm].pseudo_count(&prediction.context_index_map),
+                )
+            }
+            ArmModels::Shared(model) => {
+                let features = Self::arm_features(context, arm, self.num_arms);
+                let prediction = model.predict(&features);
+                (
+                    prediction.probability,
+                    model.pseudo_count(&prediction.context_index_map),
+                )
+            }
+        };
+
+        let score = match &self.strategy {
+            ExplorationStrategy::Ucb { exploration } => {
+                probability + *exploration / (pseudo_count + T::one()).sqrt()
+            }
+            ExplorationStrategy::Thompson => {
+                let p: f64 = probability.to_subset().unwrap_or(0.5);
+                let n: f64 = pseudo_count.to_subset().unwrap_or(0.0);
+                let beta = Beta::new(p * n + 1.0, (1.0 - p) * n + 1.0).unwrap();
+                convert(beta.sample(&mut self.rng))
+            }
+        };
+
+        ArmScore {
+            probability,
+            pseudo_count,
+            score,
+        }
+    }
+
+    fn arm_features(context: &DVector<T>, arm: usize, num_arms: usize) -> DVector<T> {
+        let mut features = context.clone().resize_vertically(context.len() + num_arms, T::zero());
+        features[context.len() + arm] = T::one();
+        features
+    }
+}
@@ -0,0 +1,42 @@
+use std::env;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+
+use gln::model::gln_model::GLN;
+use gln::model::shared_model::SharedGLN;
+use gln::server::{serve, ServerState};
+
+// Usage: gln-server MODEL_PATH [PORT] [PUBLISH_INTERVAL]
+//
+// Serves the model saved at MODEL_PATH on 127.0.0.1. `/snapshot` writes the current
+// weights back to MODEL_PATH.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} MODEL_PATH [PORT] [PUBLISH_INTERVAL]", args[0]);
+        process::exit(2);
+    }
+    let model_path = PathBuf::from(&args[1]);
+    let port: u16 = args.get(2).map_or(8080, |port| port.parse().expect("invalid port"));
+    let publish_interval: usize = args
+        .get(3)
+        .map_or(1, |interval| interval.parse().expect("invalid publish interval"));
+
+    let model: GLN = GLN::load(&model_path).unwrap_or_else(|error| {
+        eprintln!("failed to load {}: {}", model_path.display(), error);
+        process::exit(1);
+    });
+    let state = Arc::new(ServerState::new(
+        SharedGLN::new(model, publish_interval),
+        model_path,
+    ));
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).expect("failed to bind");
+    println!("listening on {}", listener.local_addr().unwrap());
+    if let Err(error) = serve(listener, state) {
+        eprintln!("server stopped: {}", error);
+        process::exit(1);
+    }
+}
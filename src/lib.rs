@@ -1,3 +1,6 @@
+pub mod bandit;
 pub mod model;
-mod optimize;
+pub mod optimize;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod utils;
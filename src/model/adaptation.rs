@@ -0,0 +1,177 @@
+use nalgebra::convert;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::utils::float::GLNFloat;
+
+// Moves the half-space boundaries of every gate while training. Every context is bounded by
+// every hyperplane of its gate, so a change resets all the weights of that gate.
+#[derive(Clone, Copy)]
+pub enum ContextAdaptationConfig<T: GLNFloat = f32> {
+    Disabled,
+    // Re-draws a hyperplane, through the current example, once more than `max_imbalance`
+    // of the `min_samples` or more examples since its last change fell on one side.
+    RedrawImbalanced { max_imbalance: T, min_samples: usize },
+    // Tracks the running median of each projection with steps of `learning_rate`, and every
+    // `interval` examples moves a bias to it when they are more than `tolerance` apart.
+    RunningMedian {
+        learning_rate: T,
+        interval: usize,
+        tolerance: T,
+    },
+}
+
+impl<T: GLNFloat> ContextAdaptationConfig<T> {
+    pub fn build(&self, context_dim: usize, seed: u64) -> Option<ContextAdaptation<T>> {
+        if let ContextAdaptationConfig::RunningMedian { interval, .. } = self {
+            assert!(*interval > 0, "the running median interval must be positive");
+        }
+        match self {
+            ContextAdaptationConfig::Disabled => None,
+            _ => Some(ContextAdaptation {
+                config: *self,
+                num_samples: vec![0; context_dim],
+                num_positives: vec![0; context_dim],
+                median_estimates: None,
+                rng: ChaCha8Rng::seed_from_u64(seed),
+            }),
+        }
+    }
+}
+
+pub enum BoundaryUpdate<T: GLNFloat = f32> {
+    Redraw { row_index: usize },
+    MoveBias { row_index: usize, bias: T },
+}
+
+// Statistics of one gate's hyperplanes since they last changed.
+#[derive(Clone)]
+pub struct ContextAdaptation<T: GLNFloat = f32> {
+    config: ContextAdaptationConfig<T>,
+    num_samples: Vec<usize>,
+    num_positives: Vec<usize>,
+    median_estimates: Option<Vec<T>>,
+    rng: ChaCha8Rng,
+}
+
+impl<T: GLNFloat> ContextAdaptation<T> {
+    pub fn rng(&mut self) -> &mut ChaCha8Rng {
+        &mut self.rng
+    }
+
+    // Records the projections of one example onto the hyperplanes with the given biases and
+    // returns the boundaries that should change.
+    pub fn observe(&mut self, projections: &[T], biases: &[T]) -> Vec<BoundaryUpdate<T>> {
+        let mut updates = Vec::new();
+        match self.config {
+            ContextAdaptationConfig::Disabled => {}
+            ContextAdaptationConfig::RedrawImbalanced {
+                max_imbalance,
+                min_samples,
+            } => {
+                for (row_index, (projection, bias)) in projections.iter().zip(biases).enumerate() {
+                    self.num_samples[row_index] += 1;
+                    if projection > bias {
+                        self.num_positives[row_index] += 1;
+                    }
+
+                    let num_samples = self.num_samples[row_index];
+                    let num_majority = self.num_positives[row_index].max(num_samples - self.num_positives[row_index]);
+                    let imbalance: T = convert(num_majority as f64 / num_samples as f64);
+                    if num_samples >= min_samples && imbalance > max_imbalance {
+                        updates.push(BoundaryUpdate::Redraw { row_index });
+                        self.num_samples[row_index] = 0;
+                        self.num_positives[row_index] = 0;
+                    }
+                }
+            }
+            ContextAdaptationConfig::RunningMedian {
+                learning_rate,
+                interval,
+                tolerance,
+            } => {
+                let estimates = self.median_estimates.get_or_insert_with(|| biases.to_vec());
+                for (row_index, projection) in projections.iter().enumerate() {
+                    let estimate = &mut estimates[row_index];
+                    if *projection > *estimate {
+                        *estimate += learning_rate;
+                    } else if *projection < *estimate {
+                        *estimate -= learning_rate;
+                    }
+
+                    self.num_samples[row_index] += 1;
+                    let distance = *estimate - biases[row_index];
+                    if self.num_samples[row_index].is_multiple_of(interval) && distance.max(-distance) > tolerance {
+                        updates.push(BoundaryUpdate::MoveBias {
+                            row_index,
+                            bias: *estimate,
+                        });
+                    }
+                }
+            }
+        }
+        updates
+    }
+
+    // Forgets the statistics of a re-drawn hyperplane.
+    pub fn reset_row(&mut self, row_index: usize, bias: T) {
+        self.num_samples[row_index] = 0;
+        self.num_positives[row_index] = 0;
+        if let Some(estimates) = self.median_estimates.as_mut() {
+            estimates[row_index] = bias;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::adaptation::{BoundaryUpdate, ContextAdaptationConfig};
+
+    #[test]
+    fn test_redraw_imbalanced_hyperplane() {
+        let config = ContextAdaptationConfig::RedrawImbalanced {
+            max_imbalance: 0.9_f64,
+            min_samples: 10,
+        };
+        let mut adaptation = config.build(2, 0).unwrap();
+        let biases = vec![0.0, 0.0];
+        for step in 0..9 {
+            let projections = vec![1.0, if step % 2 == 0 { 1.0 } else { -1.0 }];
+            assert!(adaptation.observe(&projections, &biases).is_empty());
+        }
+        let updates = adaptation.observe(&vec![1.0, 1.0], &biases);
+        assert_eq!(updates.len(), 1);
+        assert!(matches!(updates[0], BoundaryUpdate::Redraw { row_index: 0 }));
+    }
+
+    #[test]
+    fn test_running_median_moves_bias() {
+        let config = ContextAdaptationConfig::RunningMedian {
+            learning_rate: 0.1_f64,
+            interval: 200,
+            tolerance: 0.5,
+        };
+        let mut adaptation = config.build(1, 0).unwrap();
+        let biases = vec![0.0];
+        let mut updates = Vec::new();
+        for step in 0..200 {
+            updates = adaptation.observe(&vec![3.0 + (step % 3) as f64], &biases);
+        }
+        match updates.as_slice() {
+            [BoundaryUpdate::MoveBias { row_index: 0, bias }] => assert!((*bias - 4.0).abs() < 0.2),
+            _ => panic!("the bias was not moved"),
+        }
+        assert!(ContextAdaptationConfig::<f64>::Disabled.build(1, 0).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "the running median interval must be positive")]
+    fn test_running_median_rejects_zero_interval() {
+        let config = ContextAdaptationConfig::RunningMedian {
+            learning_rate: 0.1_f64,
+            interval: 0,
+            tolerance: 0.5,
+        };
+        config.build(1, 0);
+    }
+}
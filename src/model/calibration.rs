@@ -0,0 +1,315 @@
+use nalgebra::convert;
+
+use crate::model::persistence::{from_f64, to_f64, vec_from_f64, vec_to_f64, CalibrationState};
+use crate::utils::float::GLNFloat;
+use crate::utils::math::{calibration, clip_prob, logit, sigmoid};
+
+pub trait Calibrator<T: GLNFloat = f32> {
+    fn calibrate(&self, probability: T) -> T;
+    fn update(&mut self, probability: T, target: i32);
+    fn clone_box(&self) -> Box<dyn Calibrator<T> + Send + Sync>;
+    fn to_state(&self) -> CalibrationState;
+}
+
+#[derive(Clone)]
+pub enum CalibrationConfig<T: GLNFloat = f32> {
+    Disabled,
+    NegativeDownsampling { sampling_rate: T },
+    PlattScaling { learning_rate: T },
+    Isotonic { num_bins: usize },
+}
+
+impl<T: GLNFloat> CalibrationConfig<T> {
+    pub fn build(&self) -> Box<dyn Calibrator<T> + Send + Sync> {
+        match self {
+            CalibrationConfig::Disabled => Box::new(NoCalibration),
+            CalibrationConfig::NegativeDownsampling { sampling_rate } => {
+                Box::new(NegativeDownsamplingCalibrator::new(*sampling_rate))
+            }
+            CalibrationConfig::PlattScaling { learning_rate } => {
+                Box::new(PlattScalingCalibrator::new(*learning_rate))
+            }
+            CalibrationConfig::Isotonic { num_bins } => {
+                assert!(*num_bins > 0, "isotonic calibration needs at least one bin");
+                Box::new(IsotonicCalibrator::new(*num_bins))
+            }
+        }
+    }
+
+    // Configuration of the calibrator saved in `state`.
+    pub fn from_state(state: &CalibrationState) -> Self {
+        match state {
+            CalibrationState::Disabled => CalibrationConfig::Disabled,
+            CalibrationState::NegativeDownsampling { sampling_rate } => CalibrationConfig::NegativeDownsampling {
+                sampling_rate: from_f64(*sampling_rate),
+            },
+            CalibrationState::PlattScaling { learning_rate, .. } => CalibrationConfig::PlattScaling {
+                learning_rate: from_f64(*learning_rate),
+            },
+            CalibrationState::Isotonic { counts, .. } => CalibrationConfig::Isotonic { num_bins: counts.len() },
+        }
+    }
+
+    // Restores the calibrator saved in `state` with its fitted parameters.
+    pub fn build_from_state(state: &CalibrationState) -> Box<dyn Calibrator<T> + Send + Sync> {
+        match state {
+            CalibrationState::PlattScaling {
+                learning_rate,
+                scale,
+                bias,
+            } => Box::new(PlattScalingCalibrator {
+                scale: from_f64(*scale),
+                bias: from_f64(*bias),
+                learning_rate: from_f64(*learning_rate),
+            }),
+            CalibrationState::Isotonic {
+                positives,
+                counts,
+                fitted,
+            } => Box::new(IsotonicCalibrator {
+                positives: vec_from_f64(positives),
+                counts: vec_from_f64(counts),
+                fitted: vec_from_f64(fitted),
+            }),
+            _ => Self::from_state(state).build(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NoCalibration;
+
+impl<T: GLNFloat> Calibrator<T> for NoCalibration {
+    fn calibrate(&self, probability: T) -> T {
+        probability
+    }
+
+    fn update(&mut self, _probability: T, _target: i32) {}
+
+    fn clone_box(&self) -> Box<dyn Calibrator<T> + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn to_state(&self) -> CalibrationState {
+        CalibrationState::Disabled
+    }
+}
+
+// Corrects the bias introduced by keeping only `sampling_rate` of the negative examples.
+#[derive(Clone)]
+pub struct NegativeDownsamplingCalibrator<T: GLNFloat = f32> {
+    sampling_rate: T,
+}
+
+impl<T: GLNFloat> NegativeDownsamplingCalibrator<T> {
+    pub fn new(sampling_rate: T) -> Self {
+        NegativeDownsamplingCalibrator { sampling_rate }
+    }
+}
+
+impl<T: GLNFloat> Calibrator<T> for NegativeDownsamplingCalibrator<T> {
+    fn calibrate(&self, probability: T) -> T {
+        calibration(probability, self.sampling_rate)
+    }
+
+    fn update(&mut self, _probability: T, _target: i32) {}
+
+    fn clone_box(&self) -> Box<dyn Calibrator<T> + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn to_state(&self) -> CalibrationState {
+        CalibrationState::NegativeDownsampling {
+            sampling_rate: to_f64(self.sampling_rate),
+        }
+    }
+}
+
+// Fits sigmoid(a * logit(p) + b) by online gradient descent on the log loss.
+#[derive(Clone)]
+pub struct PlattScalingCalibrator<T: GLNFloat = f32> {
+    scale: T,
+    bias: T,
+    learning_rate: T,
+}
+
+impl<T: GLNFloat> PlattScalingCalibrator<T> {
+    pub fn new(learning_rate: T) -> Self {
+        PlattScalingCalibrator {
+            scale: T::one(),
+            bias: T::zero(),
+            learning_rate,
+        }
+    }
+}
+
+impl<T: GLNFloat> Calibrator<T> for PlattScalingCalibrator<T> {
+    fn calibrate(&self, probability: T) -> T {
+        sigmoid(self.scale * logit(probability) + self.bias)
+    }
+
+    fn update(&mut self, probability: T, target: i32) {
+        let input_logit = logit(probability);
+        let error = self.calibrate(probability) - convert(target as f64);
+        self.scale -= self.learning_rate * error * input_logit;
+        self.bias -= self.learning_rate * error;
+    }
+
+    fn clone_box(&self) -> Box<dyn Calibrator<T> + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn to_state(&self) -> CalibrationState {
+        CalibrationState::PlattScaling {
+            learning_rate: to_f64(self.learning_rate),
+            scale: to_f64(self.scale),
+            bias: to_f64(self.bias),
+        }
+    }
+}
+
+// Keeps label statistics in equal-width probability bins and refits a
+// non-decreasing step function with pool adjacent violators after every update.
+#[derive(Clone)]
+pub struct IsotonicCalibrator<T: GLNFloat = f32> {
+    positives: Vec<T>,
+    counts: Vec<T>,
+    fitted: Vec<T>,
+}
+
+impl<T: GLNFloat> IsotonicCalibrator<T> {
+    pub fn new(num_bins: usize) -> Self {
+        let bin_width = T::one() / convert(num_bins as f64);
+        let half: T = convert(0.5);
+        IsotonicCalibrator {
+            positives: vec![T::zero(); num_bins],
+            counts: vec![T::zero(); num_bins],
+            fitted: (0..num_bins)
+                .map(|bin| (convert::<f64, T>(bin as f64) + half) * bin_width)
+                .collect(),
+        }
+    }
+
+    fn bin_index(&self, probability: T) -> usize {
+        let num_bins = self.counts.len();
+        let scaled: f64 = (probability * convert(num_bins as f64)).to_subset().unwrap_or(0.0);
+        (scaled.max(0.0) as usize).min(num_bins - 1)
+    }
+
+    fn refit(&mut self) {
+        // Blocks of (sum of positives, count, number of bins), merged while they violate monotonicity.
+        let mut blocks: Vec<(T, T, usize)> = Vec::new();
+        for (positive, count) in self.positives.iter().zip(&self.counts) {
+            if *count == T::zero() {
+                continue;
+            }
+            blocks.push((*positive, *count, 1));
+            while blocks.len() > 1 {
+                let (last_positive, last_count, last_len) = blocks[blocks.len() - 1];
+                let (prev_positive, prev_count, prev_len) = blocks[blocks.len() - 2];
+                if prev_positive / prev_count <= last_positive / last_count {
+                    break;
+                }
+                blocks.pop();
+                let merged = blocks.last_mut().unwrap();
+                *merged = (
+                    prev_positive + last_positive,
+                    prev_count + last_count,
+                    prev_len + last_len,
+                );
+            }
+        }
+
+        let mut block_values = blocks
+            .iter()
+            .flat_map(|(positive, count, len)| std::iter::repeat_n(*positive / *count, *len));
+        let mut last_value = None;
+        for bin in 0..self.counts.len() {
+            if self.counts[bin] != T::zero() {
+                last_value = block_values.next();
+            }
+            if let Some(value) = last_value {
+                self.fitted[bin] = value;
+            }
+        }
+        // Empty bins below the first observed one take its value.
+        if let Some(first) = self.counts.iter().position(|count| *count != T::zero()) {
+            for bin in 0..first {
+                self.fitted[bin] = self.fitted[first];
+            }
+        }
+    }
+}
+
+impl<T: GLNFloat> Calibrator<T> for IsotonicCalibrator<T> {
+    fn calibrate(&self, probability: T) -> T {
+        clip_prob(self.fitted[self.bin_index(probability)], T::PRED_CLIPPING_VALUE)
+    }
+
+    fn update(&mut self, probability: T, target: i32) {
+        let bin = self.bin_index(probability);
+        self.positives[bin] += convert(target as f64);
+        self.counts[bin] += T::one();
+        self.refit();
+    }
+
+    fn clone_box(&self) -> Box<dyn Calibrator<T> + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn to_state(&self) -> CalibrationState {
+        CalibrationState::Isotonic {
+            positives: vec_to_f64(&self.positives),
+            counts: vec_to_f64(&self.counts),
+            fitted: vec_to_f64(&self.fitted),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::calibration::{
+        CalibrationConfig, Calibrator, IsotonicCalibrator, NegativeDownsamplingCalibrator,
+        NoCalibration, PlattScalingCalibrator,
+    };
+
+    #[test]
+    fn test_no_calibration() {
+        let calibrator = NoCalibration;
+        assert_eq!(Calibrator::<f32>::calibrate(&calibrator, 0.3), 0.3);
+    }
+
+    #[test]
+    fn test_negative_downsampling_calibrator() {
+        let calibrator = NegativeDownsamplingCalibrator::new(0.5_f32);
+        assert_eq!(calibrator.calibrate(0.5), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn test_platt_scaling_learns_constant_rate() {
+        let mut calibrator = PlattScalingCalibrator::new(0.05_f64);
+        for step in 0..4000 {
+            calibrator.update(0.5, (step % 4 == 0) as i32);
+        }
+        assert!((calibrator.calibrate(0.5) - 0.25).abs() < 0.05);
+    }
+
+    #[test]
+    #[should_panic(expected = "isotonic calibration needs at least one bin")]
+    fn test_isotonic_calibration_rejects_zero_bins() {
+        CalibrationConfig::<f32>::Isotonic { num_bins: 0 }.build();
+    }
+
+    #[test]
+    fn test_isotonic_calibrator_is_monotone() {
+        let mut calibrator = IsotonicCalibrator::new(4);
+        let observations = vec![(0.1, 0), (0.1, 1), (0.4, 1), (0.6, 0), (0.6, 0), (0.9, 1)];
+        for (probability, target) in observations {
+            calibrator.update(probability, target);
+        }
+        assert_eq!(calibrator.calibrate(0.1_f32), 0.4);
+        assert_eq!(calibrator.calibrate(0.4), 0.4);
+        assert_eq!(calibrator.calibrate(0.6), 0.4);
+        assert_eq!(calibrator.calibrate(0.9), 0.999);
+    }
+}
@@ -1,11 +1,165 @@
-pub struct LayerConfig {
-    pub pred_clipping_value: f32,
+use nalgebra::convert;
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+use crate::utils::float::GLNFloat;
+use crate::utils::math::{clip_hypercube, project_l2_ball, project_simplex};
+
+pub struct LayerConfig<T: GLNFloat = f32> {
+    pub pred_clipping_value: T,
 }
 
-impl LayerConfig {
+impl<T: GLNFloat> LayerConfig<T> {
     pub fn with_default_value() -> Self {
         LayerConfig {
-            pred_clipping_value: 1e-3,
+            pred_clipping_value: T::PRED_CLIPPING_VALUE,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ClassWeights<T: GLNFloat = f32> {
+    pub positive: T,
+    pub negative: T,
+}
+
+impl<T: GLNFloat> ClassWeights<T> {
+    pub fn new(positive: T, negative: T) -> Self {
+        ClassWeights { positive, negative }
+    }
+
+    pub fn with_negative_weight(negative_weight: T) -> Self {
+        ClassWeights::new(T::one(), negative_weight)
+    }
+
+    pub fn weight(&self, target: i32) -> T {
+        if target == 1 {
+            self.positive
+        } else {
+            self.negative
+        }
+    }
+}
+
+// Forgetting applied to the gate weights for nonstationary data. Every training step
+// shrinks all weights toward their initialization by `weight_decay`, and contexts that
+// have not been updated for more than `context_reset_steps` steps are reinitialized.
+#[derive(Clone, Copy)]
+pub struct ForgettingConfig<T: GLNFloat = f32> {
+    pub weight_decay: T,
+    pub context_reset_steps: Option<usize>,
+}
+
+impl<T: GLNFloat> ForgettingConfig<T> {
+    pub fn disabled() -> Self {
+        ForgettingConfig {
+            weight_decay: T::zero(),
+            context_reset_steps: None,
+        }
+    }
+}
+
+// How the neurons of a layer get their context functions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub enum ContextSharing {
+    // Every neuron samples its own hyperplanes.
+    #[default]
+    Independent,
+    // All neurons reference the hyperplanes of the first neuron, but still evaluate them separately.
+    Shared,
+    // All neurons reference one set of hyperplanes, evaluated once per example.
+    SharedEvaluation,
+}
+
+// Initial weights of every context of a layer's gates. Forgetting and drift resets shrink
+// toward these weights.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WeightInitialization<T: GLNFloat = f32> {
+    // 1 / input_dim for every input.
+    #[default]
+    Balanced,
+    Zero,
+    // Independent uniform weights in [low, high), drawn from `seed`.
+    Uniform { low: T, high: T, seed: u64 },
+    // Weight 1 on the input at `input_index` and 0 elsewhere, passing that input through.
+    Identity { input_index: usize },
+}
+
+// Convex set a neuron's weights are projected back onto after every gradient step. The
+// radius of the set is the neuron's weight clipping value.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub enum WeightProjection {
+    // Clips every weight to [-radius, radius].
+    #[default]
+    Hypercube,
+    // Clips every weight to [0, radius], so the output is monotone in every input.
+    NonNegative,
+    // Rescales the weights onto the Euclidean ball of the radius when they leave it.
+    L2Ball,
+    // Non-negative weights summing to 1, regardless of the radius.
+    Simplex,
+}
+
+impl WeightProjection {
+    pub fn project<T: GLNFloat>(&self, weights: &mut [T], radius: T) {
+        match self {
+            WeightProjection::Hypercube => {
+                for weight in weights.iter_mut() {
+                    *weight = clip_hypercube(*weight, radius);
+                }
+            }
+            WeightProjection::NonNegative => {
+                for weight in weights.iter_mut() {
+                    *weight = weight.max(T::zero()).min(radius);
+                }
+            }
+            WeightProjection::L2Ball => project_l2_ball(weights, radius),
+            WeightProjection::Simplex => project_simplex(weights),
+        }
+    }
+
+    // Number of weights the projection holds on the boundary of the set: at a bound of the
+    // hypercube or the non-negative box, at 0 on the simplex, and all of them on the sphere.
+    pub fn num_saturated<T: GLNFloat>(&self, weights: &[T], radius: T) -> usize {
+        match self {
+            WeightProjection::Hypercube => weights.iter().filter(|weight| weight.abs() >= radius).count(),
+            WeightProjection::NonNegative => weights
+                .iter()
+                .filter(|weight| **weight <= T::zero() || **weight >= radius)
+                .count(),
+            WeightProjection::L2Ball => {
+                let norm = weights.iter().fold(T::zero(), |acc, weight| acc + *weight * *weight).sqrt();
+                // Rescaled weights land on the sphere up to rounding.
+                if norm >= radius * (T::one() - convert(1e-6)) {
+                    weights.len()
+                } else {
+                    0
+                }
+            }
+            WeightProjection::Simplex => weights.iter().filter(|weight| **weight <= T::zero()).count(),
+        }
+    }
+}
+
+// Direction in which the output may move when a base input increases. Weights on a constrained
+// input are kept non-negative (increasing) or non-positive (decreasing).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub enum Monotonicity {
+    #[default]
+    Unconstrained,
+    Increasing,
+    Decreasing,
+}
+
+impl Monotonicity {
+    pub fn constrain<T: GLNFloat>(&self, weight: T) -> T {
+        match self {
+            Monotonicity::Unconstrained => weight,
+            Monotonicity::Increasing => weight.max(T::zero()),
+            Monotonicity::Decreasing => weight.min(T::zero()),
         }
     }
 }
@@ -1,62 +1,332 @@
+use crate::model::adaptation::{BoundaryUpdate, ContextAdaptation};
+use crate::model::persistence::{vec_from_f64, vec_to_f64, HalfSpaceContextState};
+use crate::utils::data_type::ContextIndex;
+use crate::utils::float::GLNFloat;
 use crate::utils::math::norm;
-use rand::thread_rng;
+use nalgebra::{convert, DMatrix, DVector};
+use rand::{thread_rng, Rng};
+use std::sync::Arc;
 use rand_distr::{Distribution, Normal};
 
-pub trait ContextFunction {
-    fn indicator_func(&self, side_info: &[f32]) -> Vec<bool>;
+pub trait ContextFunction<T: GLNFloat = f32> {
+    fn indicator_func(&self, side_info: &[T]) -> Vec<bool>;
+
+    // Number of indicator bits, so a gate over this function has `2^context_dim` contexts.
+    fn context_dim(&self) -> usize;
+
+    // Reads the indicator bits as a binary number, lowest bit first, to index the weights.
+    fn context_index(&self, side_info: &[T]) -> ContextIndex {
+        self.indicator_func(side_info)
+            .iter()
+            .enumerate()
+            .map(|(index, bit)| (*bit as ContextIndex) << index)
+            .sum()
+    }
+
+    // Evaluates the contexts of all neurons of a layer at once. Layers of a type without an
+    // evaluator call `context_index` of every neuron.
+    fn layer_evaluator(_contexts: &[&Self]) -> Option<LayerContextEvaluator<T>>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    // Moves the boundaries by the adaptation statistics of one example and returns the bits
+    // whose boundaries moved. Only half-space contexts adapt, the others never change.
+    fn adapt(&mut self, _adaptation: &mut ContextAdaptation<T>, _side_info: &[T]) -> ContextIndex {
+        0
+    }
 }
 
-pub struct HalfSpaceContext {
+// How `HalfSpaceContext::from_data` places the hyperplanes relative to a calibration sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HyperplaneSampling {
+    // Unit Gaussian directions and Gaussian biases, ignoring the data.
+    Gaussian,
+    // Unit Gaussian directions with biases at stratified quantiles of the projected data.
+    DataQuantileBias,
+    // Unit Gaussian directions, each passing through a randomly chosen data point.
+    ThroughDataPoints,
+    // Splits on a single random feature at a stratified quantile of its values.
+    AxisAligned,
+    // Directions orthogonalized in blocks of `feature_dim`, with data quantile biases.
+    Orthogonal,
+}
+
+// The hyperplanes are reference counted, so clones of a context share their memory.
+#[derive(Clone, PartialEq)]
+pub struct HalfSpaceContext<T: GLNFloat = f32> {
     feature_dim: usize,
     context_dim: usize,
-    context_maps: Vec<Vec<f32>>,
-    context_bias: Vec<f32>,
+    context_maps: Arc<Vec<Vec<T>>>,
+    context_bias: Arc<Vec<T>>,
+    // Features every hyperplane ignores, including the ones redrawn later.
+    masked_features: Vec<usize>,
 }
 
-impl HalfSpaceContext {
+impl<T: GLNFloat> HalfSpaceContext<T> {
     pub fn new(context_dim: usize, feature_dim: usize) -> Self {
+        Self::from_rng(context_dim, feature_dim, &mut thread_rng())
+    }
+
+    // Samples the hyperplanes from `rng`, so that a seeded rng gives reproducible contexts.
+    pub fn from_rng<R: Rng>(context_dim: usize, feature_dim: usize, rng: &mut R) -> Self {
         let normal = Normal::new(0.0, 1.0).unwrap();
 
-        let mut rng = thread_rng();
-        // let mut rng_with_seed = ChaCha8Rng::seed_from_u64(2);
-        // let mut rng = ChaCha8Rng::seed_from_u64(2);
-        let context_maps: Vec<Vec<f32>> = (0..context_dim)
+        let context_maps: Vec<Vec<T>> = (0..context_dim)
             .into_iter()
             .map(|_| {
                 normal
-                    .sample_iter(&mut rng)
+                    .sample_iter(&mut *rng)
                     .take(feature_dim)
-                    .collect::<Vec<f32>>()
+                    .map(|value: f64| convert(value))
+                    .collect::<Vec<T>>()
             })
             .collect();
-        let norm_vec: Vec<f32> = context_maps.iter().map(|x| norm(x)).collect();
+        let norm_vec: Vec<T> = context_maps.iter().map(|x| norm(x)).collect();
 
-        let normalized_context_maps: Vec<Vec<f32>> = context_maps
+        let normalized_context_maps: Vec<Vec<T>> = context_maps
             .iter()
             .zip(norm_vec)
-            .map(|(vec, norm)| vec.iter().map(|value| value / norm).collect::<Vec<f32>>())
+            .map(|(vec, norm)| vec.iter().map(|value| *value / norm).collect::<Vec<T>>())
             .collect();
 
-        let context_bias: Vec<f32> = normal
-            .sample_iter(&mut thread_rng())
+        let context_bias: Vec<T> = normal
+            .sample_iter(&mut *rng)
             .take(context_dim)
+            .map(|value: f64| convert(value))
             .collect();
 
         HalfSpaceContext {
             feature_dim,
             context_dim,
-            context_maps: normalized_context_maps,
-            context_bias,
+            context_maps: Arc::new(normalized_context_maps),
+            context_bias: Arc::new(context_bias),
+            masked_features: Vec::new(),
+        }
+    }
+
+    // Places the hyperplanes with `sampling` using `calibration_data`, e.g. a warm-up sample of
+    // the side information. Panics if the data is empty unless `sampling` is `Gaussian`, or if
+    // a point does not have `feature_dim` features.
+    pub fn from_data<R: Rng>(
+        context_dim: usize,
+        feature_dim: usize,
+        calibration_data: &[DVector<T>],
+        sampling: HyperplaneSampling,
+        rng: &mut R,
+    ) -> Self {
+        assert!(
+            calibration_data.iter().all(|point| point.len() == feature_dim),
+            "every calibration point must have {} features",
+            feature_dim
+        );
+        if sampling == HyperplaneSampling::Gaussian {
+            return Self::from_rng(context_dim, feature_dim, rng);
+        }
+        assert!(!calibration_data.is_empty(), "calibration data is empty");
+
+        let mut context_maps: Vec<Vec<T>> = Vec::with_capacity(context_dim);
+        let mut context_bias = Vec::with_capacity(context_dim);
+        for row_index in 0..context_dim {
+            // Quantile levels are spread over (0, 1) so that the splits cover the whole sample.
+            let level: T = convert((row_index as f64 + rng.gen::<f64>()) / context_dim as f64);
+            let direction = match sampling {
+                HyperplaneSampling::AxisAligned => {
+                    let mut direction = vec![T::zero(); feature_dim];
+                    direction[rng.gen_range(0..feature_dim)] = T::one();
+                    direction
+                }
+                HyperplaneSampling::Orthogonal => {
+                    let block_start = row_index - row_index % feature_dim.max(1);
+                    orthogonal_direction(&context_maps[block_start..], feature_dim, rng)
+                }
+                _ => gaussian_direction(feature_dim, rng),
+            };
+
+            let projections: Vec<T> = calibration_data
+                .iter()
+                .map(|point| dot(&direction, point.as_slice()))
+                .collect();
+            let bias = match sampling {
+                HyperplaneSampling::ThroughDataPoints => projections[rng.gen_range(0..projections.len())],
+                _ => quantile(projections, level),
+            };
+            context_maps.push(direction);
+            context_bias.push(bias);
+        }
+
+        HalfSpaceContext {
+            feature_dim,
+            context_dim,
+            context_maps: Arc::new(context_maps),
+            context_bias: Arc::new(context_bias),
+            masked_features: Vec::new(),
+        }
+    }
+
+    pub fn resample<R: Rng>(&mut self, rng: &mut R) {
+        let masked_features = std::mem::take(&mut self.masked_features);
+        *self = Self::from_rng(self.context_dim, self.feature_dim, rng);
+        self.mask_features(&masked_features);
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.feature_dim
+    }
+
+    pub fn biases(&self) -> &[T] {
+        &self.context_bias
+    }
+
+    pub fn projections(&self, side_info: &[T]) -> Vec<T> {
+        self.context_maps.iter().map(|map| dot(map, side_info)).collect()
+    }
+
+    // Replaces one hyperplane by a Gaussian direction passing through `side_info`. A context
+    // sharing the hyperplanes gets its own copy first.
+    pub fn redraw_hyperplane<R: Rng>(&mut self, row_index: usize, side_info: &[T], rng: &mut R) -> T {
+        let mut direction = gaussian_direction(self.feature_dim, rng);
+        for index in self.masked_features.iter() {
+            direction[*index] = T::zero();
         }
+        let bias = dot(&direction, side_info);
+        Arc::make_mut(&mut self.context_maps)[row_index] = direction;
+        Arc::make_mut(&mut self.context_bias)[row_index] = bias;
+        bias
+    }
+
+    pub fn set_bias(&mut self, row_index: usize, bias: T) {
+        Arc::make_mut(&mut self.context_bias)[row_index] = bias;
     }
+
+    // Replaces the first hyperplanes by those of `source`, which must take the same features,
+    // so that both contexts agree on the low bits of the context index.
+    pub fn copy_hyperplanes_from(&mut self, source: &HalfSpaceContext<T>) {
+        assert_eq!(self.feature_dim, source.feature_dim, "the source context takes other features");
+        let num_shared = self.context_dim.min(source.context_dim);
+        Arc::make_mut(&mut self.context_maps)[..num_shared].clone_from_slice(&source.context_maps[..num_shared]);
+        Arc::make_mut(&mut self.context_bias)[..num_shared].copy_from_slice(&source.context_bias[..num_shared]);
+    }
+
+    // Zeroes the coefficients of the given features, so the context does not depend on them.
+    pub fn mask_features(&mut self, feature_indices: &[usize]) {
+        self.masked_features = feature_indices.to_vec();
+        let is_masked = self
+            .context_maps
+            .iter()
+            .all(|map| feature_indices.iter().all(|index| map[*index] == T::zero()));
+        if !is_masked {
+            for map in Arc::make_mut(&mut self.context_maps).iter_mut() {
+                for index in feature_indices {
+                    map[*index] = T::zero();
+                }
+            }
+        }
+    }
+
+    pub fn shares_hyperplanes_with(&self, other: &HalfSpaceContext<T>) -> bool {
+        Arc::ptr_eq(&self.context_maps, &other.context_maps)
+    }
+
+    pub fn to_state(&self) -> HalfSpaceContextState {
+        HalfSpaceContextState {
+            feature_dim: self.feature_dim,
+            context_dim: self.context_dim,
+            context_maps: self.context_maps.iter().map(|map| vec_to_f64(map)).collect(),
+            context_bias: vec_to_f64(&self.context_bias),
+        }
+    }
+
+    pub fn from_state(state: &HalfSpaceContextState) -> Self {
+        HalfSpaceContext {
+            feature_dim: state.feature_dim,
+            context_dim: state.context_dim,
+            context_maps: Arc::new(state.context_maps.iter().map(|map| vec_from_f64(map)).collect()),
+            context_bias: Arc::new(vec_from_f64(&state.context_bias)),
+            masked_features: Vec::new(),
+        }
+    }
+}
+
+fn dot<T: GLNFloat>(left: &[T], right: &[T]) -> T {
+    left.iter()
+        .zip(right)
+        .fold(T::zero(), |acc, (l, r)| acc + *l * *r)
+}
+
+fn gaussian_direction<T: GLNFloat, R: Rng>(feature_dim: usize, rng: &mut R) -> Vec<T> {
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let direction: Vec<T> = normal
+        .sample_iter(&mut *rng)
+        .take(feature_dim)
+        .map(|value: f64| convert(value))
+        .collect();
+    let direction_norm = norm(&direction);
+    direction.iter().map(|value| *value / direction_norm).collect()
 }
 
-impl ContextFunction for HalfSpaceContext {
-    fn indicator_func(&self, side_info: &[f32]) -> Vec<bool> {
+// Gaussian direction made orthogonal to `basis` by Gram-Schmidt, resampled if it degenerates.
+fn orthogonal_direction<T: GLNFloat, R: Rng>(basis: &[Vec<T>], feature_dim: usize, rng: &mut R) -> Vec<T> {
+    loop {
+        let mut direction = gaussian_direction(feature_dim, rng);
+        for basis_vector in basis {
+            let projection = dot(&direction, basis_vector);
+            for (value, basis_value) in direction.iter_mut().zip(basis_vector) {
+                *value -= projection * *basis_value;
+            }
+        }
+        let direction_norm = norm(&direction);
+        if direction_norm > convert(1e-6) {
+            return direction.iter().map(|value| *value / direction_norm).collect();
+        }
+    }
+}
+
+// Linearly interpolated quantile of `values` at `level` in [0, 1].
+fn quantile<T: GLNFloat>(mut values: Vec<T>, level: T) -> T {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let position: f64 = (level * convert((values.len() - 1) as f64)).to_subset().unwrap();
+    let lower = position.floor() as usize;
+    let upper = (lower + 1).min(values.len() - 1);
+    let fraction: T = convert(position - lower as f64);
+    values[lower] + (values[upper] - values[lower]) * fraction
+}
+
+impl<T: GLNFloat> ContextFunction<T> for HalfSpaceContext<T> {
+    fn context_dim(&self) -> usize {
+        self.context_dim
+    }
+
+    fn layer_evaluator(contexts: &[&Self]) -> Option<LayerContextEvaluator<T>> {
+        Some(LayerContextEvaluator::new(contexts))
+    }
+
+    fn adapt(&mut self, adaptation: &mut ContextAdaptation<T>, side_info: &[T]) -> ContextIndex {
+        let projections = self.projections(side_info);
+        let mut changed_bits = 0;
+        for update in adaptation.observe(&projections, self.biases()) {
+            match update {
+                BoundaryUpdate::Redraw { row_index } => {
+                    let bias = self.redraw_hyperplane(row_index, side_info, adaptation.rng());
+                    adaptation.reset_row(row_index, bias);
+                    changed_bits |= 1 << row_index;
+                }
+                BoundaryUpdate::MoveBias { row_index, bias } => {
+                    self.set_bias(row_index, bias);
+                    changed_bits |= 1 << row_index;
+                }
+            }
+        }
+        changed_bits
+    }
+
+    fn indicator_func(&self, side_info: &[T]) -> Vec<bool> {
         let mut results = Vec::with_capacity(self.context_dim);
         // split space by x . v  > b
         for row_index in 0..self.context_dim {
-            let mut value = 0.0;
+            let mut value = T::zero();
             for col_index in 0..self.feature_dim {
                 value += self.context_maps[row_index][col_index] * side_info[col_index];
             }
@@ -64,24 +334,200 @@ impl ContextFunction for HalfSpaceContext {
         }
         results
     }
+
+    // Same as the default, without collecting the bits.
+    fn context_index(&self, side_info: &[T]) -> ContextIndex {
+        let mut context_index = 0;
+        for row_index in 0..self.context_dim {
+            let value = self.context_maps[row_index]
+                .iter()
+                .zip(side_info)
+                .fold(T::zero(), |acc, (weight, feature)| acc + *weight * *feature);
+            if value > self.context_bias[row_index] {
+                context_index |= 1 << row_index;
+            }
+        }
+        context_index
+    }
 }
 
-pub struct SkipGramContext {}
+// Hyperplanes of all the neurons in a layer stacked into one matrix, so that every
+// neuron's context is computed with a single matrix-vector product.
+#[derive(Clone)]
+pub struct LayerContextEvaluator<T: GLNFloat = f32> {
+    hyperplanes: DMatrix<T>,
+    biases: DVector<T>,
+    context_dims: Vec<usize>,
+}
+
+impl<T: GLNFloat> LayerContextEvaluator<T> {
+    pub fn new(contexts: &[&HalfSpaceContext<T>]) -> Self {
+        let feature_dim = contexts.first().map_or(0, |context| context.feature_dim);
+        let rows: Vec<&Vec<T>> = contexts.iter().flat_map(|context| context.context_maps.iter()).collect();
+        let biases = contexts.iter().flat_map(|context| context.context_bias.iter().cloned());
+
+        LayerContextEvaluator {
+            hyperplanes: DMatrix::from_fn(rows.len(), feature_dim, |row, col| rows[row][col]),
+            biases: DVector::from_iterator(rows.len(), biases),
+            context_dims: contexts.iter().map(|context| context.context_dim).collect(),
+        }
+    }
 
-impl ContextFunction for SkipGramContext {
-    fn indicator_func(&self, _side_info: &[f32]) -> Vec<bool> {
-        todo!()
+    // Writes the context index of every neuron into `context_indices`, using `projections`
+    // as scratch space for the product.
+    pub fn context_indices_into(
+        &self,
+        side_info: &DVector<T>,
+        projections: &mut DVector<T>,
+        context_indices: &mut Vec<ContextIndex>,
+    ) {
+        if projections.len() != self.biases.len() {
+            *projections = DVector::zeros(self.biases.len());
+        }
+        projections.gemv(T::one(), &self.hyperplanes, side_info, T::zero());
+
+        context_indices.clear();
+        let mut row = 0;
+        for context_dim in self.context_dims.iter() {
+            let mut context_index = 0;
+            for bit in 0..*context_dim {
+                if projections[row + bit] > self.biases[row + bit] {
+                    context_index |= 1 << bit;
+                }
+            }
+            context_indices.push(context_index);
+            row += context_dim;
+        }
+    }
+}
+
+// Hashes the categorical IDs at `feature_indices` of the side information into one of
+// `2^context_dim` buckets, so that every combination of values has its own context up to
+// collisions. IDs are read as integers, e.g. country or campaign codes encoded as floats.
+#[derive(Clone, PartialEq)]
+pub struct CategoricalContext {
+    feature_indices: Vec<usize>,
+    context_dim: usize,
+    seed: u64,
+}
+
+impl CategoricalContext {
+    pub fn new(feature_indices: Vec<usize>, context_dim: usize, seed: u64) -> Self {
+        CategoricalContext {
+            feature_indices,
+            context_dim,
+            seed,
+        }
+    }
+
+    pub fn feature_indices(&self) -> &[usize] {
+        &self.feature_indices
+    }
+
+    // FNV-1a over the IDs, which unlike `DefaultHasher` is stable across Rust releases,
+    // so saved weight tables keep their buckets.
+    pub fn bucket<T: GLNFloat>(&self, side_info: &[T]) -> ContextIndex {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325 ^ self.seed;
+        for feature_index in self.feature_indices.iter() {
+            let value: f64 = side_info[*feature_index].to_subset().unwrap();
+            for byte in (value.round() as i64).to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        (hash % (1u64 << self.context_dim)) as ContextIndex
+    }
+}
+
+impl<T: GLNFloat> ContextFunction<T> for CategoricalContext {
+    fn context_dim(&self) -> usize {
+        self.context_dim
+    }
+
+    fn indicator_func(&self, side_info: &[T]) -> Vec<bool> {
+        let bucket = self.bucket(side_info);
+        (0..self.context_dim).map(|bit| bucket >> bit & 1 == 1).collect()
+    }
+
+    fn context_index(&self, side_info: &[T]) -> ContextIndex {
+        self.bucket(side_info)
+    }
+}
+
+// Puts every example in the single context 0, so a gate over it holds one weight vector
+// and its neuron is a plain geometric mixer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FixedContext;
+
+impl<T: GLNFloat> ContextFunction<T> for FixedContext {
+    fn context_dim(&self) -> usize {
+        0
+    }
+
+    fn indicator_func(&self, _side_info: &[T]) -> Vec<bool> {
+        Vec::new()
+    }
+
+    fn context_index(&self, _side_info: &[T]) -> ContextIndex {
+        0
+    }
+}
+
+// Concatenates the bits of two context functions, `first`'s being the low bits, so that the
+// contexts are the product of both partitions. Nest it to combine more than two, e.g.
+// `ConcatContext::new(ConcatContext::new(half_space, categorical), time_of_day)`.
+#[derive(Clone, PartialEq)]
+pub struct ConcatContext<A, B> {
+    first: A,
+    second: B,
+}
+
+pub type ProductContext<A, B> = ConcatContext<A, B>;
+
+impl<A, B> ConcatContext<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        ConcatContext { first, second }
+    }
+
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+}
+
+impl<T: GLNFloat, A: ContextFunction<T>, B: ContextFunction<T>> ContextFunction<T> for ConcatContext<A, B> {
+    fn context_dim(&self) -> usize {
+        self.first.context_dim() + self.second.context_dim()
+    }
+
+    fn indicator_func(&self, side_info: &[T]) -> Vec<bool> {
+        let mut bits = self.first.indicator_func(side_info);
+        bits.extend(self.second.indicator_func(side_info));
+        bits
+    }
+
+    fn context_index(&self, side_info: &[T]) -> ContextIndex {
+        self.first.context_index(side_info) | self.second.context_index(side_info) << self.first.context_dim()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::model::context_func::{ContextFunction, HalfSpaceContext};
+    use crate::model::context_func::{
+        dot, CategoricalContext, ConcatContext, ContextFunction,
+        FixedContext, HalfSpaceContext, HyperplaneSampling, LayerContextEvaluator,
+    };
+    use nalgebra::DVector;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
 
     #[test]
     fn test_new_half_space_context() {
         // このテストは後々いらない。初期化がうまく出来ているかチェックするためだけ。
-        let actual = HalfSpaceContext::new(2, 3);
+        let actual = HalfSpaceContext::<f32>::new(2, 3);
     }
 
     #[test]
@@ -89,7 +535,7 @@ mod test {
         let side_info = vec![1.2, 1.5, 0.9];
         let context_dim = 4;
         let feature_dim = 3;
-        let half_space_context = HalfSpaceContext::new(context_dim, feature_dim);
+        let half_space_context = HalfSpaceContext::<f32>::new(context_dim, feature_dim);
         // TODO: テストできないので、乱数の生成はシードを固定できるようにする。
         let actual = half_space_context.indicator_func(&side_info);
 
@@ -97,4 +543,139 @@ mod test {
             println!("{}", bit);
         }
     }
+
+    #[test]
+    fn test_half_space_context_from_seeded_rng() {
+        let first = HalfSpaceContext::<f32>::from_rng(4, 3, &mut ChaCha8Rng::seed_from_u64(3));
+        let second = HalfSpaceContext::<f32>::from_rng(4, 3, &mut ChaCha8Rng::seed_from_u64(3));
+        assert!(first == second);
+    }
+
+    #[test]
+    fn test_half_space_context_index_matches_indicator_bits() {
+        let context = HalfSpaceContext::<f64>::from_rng(5, 3, &mut ChaCha8Rng::seed_from_u64(7));
+        for side_info in vec![vec![0.1, -0.8, 0.4], vec![1.5, 0.2, -0.3], vec![-1.0, -1.0, 2.0]] {
+            let expected: usize = context
+                .indicator_func(&side_info)
+                .iter()
+                .enumerate()
+                .map(|(index, bit)| (*bit as usize) << index)
+                .sum();
+            assert_eq!(context.context_index(&side_info), expected);
+        }
+    }
+
+    #[test]
+    fn test_layer_context_evaluator_matches_per_neuron_indices() {
+        let mut rng = ChaCha8Rng::seed_from_u64(11);
+        let contexts: Vec<HalfSpaceContext<f32>> =
+            (0..6).map(|_| HalfSpaceContext::from_rng(4, 5, &mut rng)).collect();
+        let evaluator = LayerContextEvaluator::new(&contexts.iter().collect::<Vec<_>>());
+
+        let mut projections = DVector::zeros(0);
+        let mut context_indices = Vec::new();
+        for _ in 0..200 {
+            let side_info = DVector::from_fn(5, |_, _| rng.gen_range(-2.0..2.0));
+            evaluator.context_indices_into(&side_info, &mut projections, &mut context_indices);
+            let expected: Vec<usize> = contexts
+                .iter()
+                .map(|context| context.context_index(side_info.as_slice()))
+                .collect();
+            assert_eq!(context_indices, expected);
+        }
+    }
+
+    fn uncentered_data(rng: &mut ChaCha8Rng) -> Vec<DVector<f64>> {
+        (0..100)
+            .map(|_| DVector::from_vec(vec![rng.gen_range(95.0..105.0), rng.gen_range(-0.01..0.01), rng.gen_range(0.0..1.0)]))
+            .collect()
+    }
+
+    fn splits_data(context: &HalfSpaceContext<f64>, data: &[DVector<f64>]) -> bool {
+        (0..context.context_dim).all(|row_index| {
+            let num_positive = data
+                .iter()
+                .filter(|point| context.indicator_func(point.as_slice())[row_index])
+                .count();
+            num_positive > 0 && num_positive < data.len()
+        })
+    }
+
+    #[test]
+    fn test_data_dependent_hyperplanes_split_uncentered_data() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let data = uncentered_data(&mut rng);
+        for sampling in vec![
+            HyperplaneSampling::DataQuantileBias,
+            HyperplaneSampling::AxisAligned,
+            HyperplaneSampling::Orthogonal,
+        ] {
+            let context = HalfSpaceContext::from_data(6, 3, &data, sampling, &mut rng);
+            assert!(splits_data(&context, &data));
+        }
+
+        let context = HalfSpaceContext::from_data(6, 3, &data, HyperplaneSampling::ThroughDataPoints, &mut rng);
+        for (map, bias) in context.context_maps.iter().zip(context.context_bias.iter()) {
+            assert!(data.iter().any(|point| (dot(map, point.as_slice()) - *bias).abs() < 1e-12));
+        }
+    }
+
+    #[test]
+    fn test_axis_aligned_and_orthogonal_directions() {
+        let mut rng = ChaCha8Rng::seed_from_u64(6);
+        let data = uncentered_data(&mut rng);
+
+        let context = HalfSpaceContext::from_data(4, 3, &data, HyperplaneSampling::AxisAligned, &mut rng);
+        for map in context.context_maps.iter() {
+            assert_eq!(map.iter().filter(|value| **value == 1.0).count(), 1);
+            assert_eq!(map.iter().filter(|value| **value == 0.0).count(), 2);
+        }
+
+        let context = HalfSpaceContext::from_data(5, 3, &data, HyperplaneSampling::Orthogonal, &mut rng);
+        // Directions are orthonormal within each block of feature_dim = 3.
+        for (row, left) in context.context_maps.iter().enumerate() {
+            for (col, right) in context.context_maps.iter().enumerate() {
+                if row / 3 == col / 3 {
+                    let expected = if row == col { 1.0 } else { 0.0 };
+                    assert!((dot(left, right) - expected).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_categorical_context_hashes_ids_into_buckets() {
+        let context = CategoricalContext::new(vec![0, 2], 3, 1);
+        let index = ContextFunction::<f64>::context_index(&context, &[44.0, 0.5, 7.0]);
+        assert!(index < 8);
+        assert_eq!(context.context_index(&[44.0, -3.0, 7.0]), index);
+
+        let bits = ContextFunction::<f64>::indicator_func(&context, &[44.0, 0.5, 7.0]);
+        let from_bits: usize = bits.iter().enumerate().map(|(bit, set)| (*set as usize) << bit).sum();
+        assert_eq!(from_bits, index);
+
+        let buckets: std::collections::HashSet<usize> = (0..100)
+            .map(|id| context.context_index(&[id as f64, 0.0, 7.0]))
+            .collect();
+        assert_eq!(buckets.len(), 8);
+    }
+
+    #[test]
+    fn test_concat_context_appends_bits() {
+        let mut rng = ChaCha8Rng::seed_from_u64(8);
+        let half_space = HalfSpaceContext::<f64>::from_rng(2, 3, &mut rng);
+        let categorical = CategoricalContext::new(vec![2], 3, 0);
+        let context = ConcatContext::new(ConcatContext::new(half_space.clone(), FixedContext), categorical.clone());
+        assert_eq!(context.context_dim(), 5);
+
+        for _ in 0..50 {
+            let side_info = [rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0), rng.gen_range(0..10) as f64];
+            let bits = context.indicator_func(&side_info);
+            let mut expected = half_space.indicator_func(&side_info);
+            expected.extend(categorical.indicator_func(&side_info));
+            assert_eq!(bits, expected);
+            let from_bits: usize = bits.iter().enumerate().map(|(bit, set)| (*set as usize) << bit).sum();
+            assert_eq!(context.context_index(&side_info), from_bits);
+        }
+    }
 }
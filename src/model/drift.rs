@@ -0,0 +1,100 @@
+use nalgebra::convert;
+
+use crate::utils::float::GLNFloat;
+
+pub trait DriftDetector<T: GLNFloat = f32> {
+    // Returns true when a drift is detected at this observation.
+    fn update(&mut self, value: T) -> bool;
+    fn reset(&mut self);
+    fn clone_box(&self) -> Box<dyn DriftDetector<T> + Send + Sync>;
+}
+
+#[derive(Clone)]
+pub enum DriftDetectorConfig<T: GLNFloat = f32> {
+    PageHinkley {
+        delta: T,
+        threshold: T,
+        min_samples: usize,
+    },
+}
+
+impl<T: GLNFloat> DriftDetectorConfig<T> {
+    pub fn build(&self) -> Box<dyn DriftDetector<T> + Send + Sync> {
+        match self {
+            DriftDetectorConfig::PageHinkley {
+                delta,
+                threshold,
+                min_samples,
+            } => Box::new(PageHinkley::new(*delta, *threshold, *min_samples)),
+        }
+    }
+}
+
+// Page-Hinkley test for an increase in the mean of the observed values,
+// e.g. the streaming loss of the output neuron.
+#[derive(Clone)]
+pub struct PageHinkley<T: GLNFloat = f32> {
+    delta: T,
+    threshold: T,
+    min_samples: usize,
+    num_samples: usize,
+    mean: T,
+    cumulative_sum: T,
+    min_cumulative_sum: T,
+}
+
+impl<T: GLNFloat> PageHinkley<T> {
+    pub fn new(delta: T, threshold: T, min_samples: usize) -> Self {
+        PageHinkley {
+            delta,
+            threshold,
+            min_samples,
+            num_samples: 0,
+            mean: T::zero(),
+            cumulative_sum: T::zero(),
+            min_cumulative_sum: T::zero(),
+        }
+    }
+}
+
+impl<T: GLNFloat> DriftDetector<T> for PageHinkley<T> {
+    fn update(&mut self, value: T) -> bool {
+        self.num_samples += 1;
+        self.mean += (value - self.mean) / convert(self.num_samples as f64);
+        self.cumulative_sum += value - self.mean - self.delta;
+        self.min_cumulative_sum = self.min_cumulative_sum.min(self.cumulative_sum);
+
+        self.num_samples >= self.min_samples
+            && self.cumulative_sum - self.min_cumulative_sum > self.threshold
+    }
+
+    fn reset(&mut self) {
+        self.num_samples = 0;
+        self.mean = T::zero();
+        self.cumulative_sum = T::zero();
+        self.min_cumulative_sum = T::zero();
+    }
+
+    fn clone_box(&self) -> Box<dyn DriftDetector<T> + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::drift::{DriftDetector, PageHinkley};
+
+    #[test]
+    fn test_page_hinkley_detects_mean_shift() {
+        let mut detector = PageHinkley::new(0.005_f32, 5.0, 30);
+        for _ in 0..200 {
+            assert!(!detector.update(0.2));
+        }
+        let detected_at = (0..200).position(|_| detector.update(1.0));
+        assert!(detected_at.is_some());
+        assert!(detected_at.unwrap() < 20);
+
+        detector.reset();
+        assert!(!detector.update(1.0));
+    }
+}
@@ -1,67 +1,393 @@
-use nalgebra::DVector;
+use std::borrow::Cow;
 
+use nalgebra::{convert, DVector};
+use rand::Rng;
+
+use crate::model::adaptation::ContextAdaptation;
+use crate::model::config::ForgettingConfig;
 use crate::model::context_func::ContextFunction;
 use crate::model::context_func::HalfSpaceContext;
+use crate::model::persistence::{from_f64, to_f64, vec_from_f64, vec_to_f64, GateState};
 use crate::utils::data_type::ContextIndex;
+use crate::utils::float::GLNFloat;
 
-pub struct Gate<C: ContextFunction> {
-    weights: Vec<Vec<f32>>,
+#[derive(Clone)]
+pub struct Gate<C: ContextFunction<T>, T: GLNFloat = f32> {
+    weights: Vec<Vec<T>>,
     context_func: C,
+    initial_weights: Vec<Vec<T>>,
+    // Number of updates applied to this gate, and the step at which each context was last updated.
+    step: usize,
+    last_updated: Vec<usize>,
+    visit_counts: Vec<usize>,
+    forgetting: ForgettingConfig<T>,
+    // Not persisted, a loaded gate keeps its boundaries fixed until adaptation is set again.
+    adaptation: Option<ContextAdaptation<T>>,
 }
 
-impl Gate<HalfSpaceContext> {
+impl<T: GLNFloat> Gate<HalfSpaceContext<T>, T> {
     pub fn new<F>(
         input_dim: usize,
         context_dim: usize,
         feature_dim: usize,
         weight_init_func: F,
-    ) -> Gate<HalfSpaceContext>
+    ) -> Gate<HalfSpaceContext<T>, T>
         where
-            F: Fn(usize, usize) -> Vec<Vec<f32>>,
+            F: FnOnce(usize, usize) -> Vec<Vec<T>>,
     {
+        Gate::from_context_func(
+            input_dim,
+            HalfSpaceContext::new(context_dim, feature_dim),
+            weight_init_func,
+        )
+    }
+
+    pub fn resample_context<R: Rng>(&mut self, rng: &mut R) {
+        self.context_func.resample(rng);
+    }
+
+    pub fn to_state(&self) -> GateState {
+        GateState {
+            context: self.context_func.to_state(),
+            weights: self.weights.iter().map(|weights| vec_to_f64(weights)).collect(),
+            initial_weights: self
+                .initial_weights
+                .iter()
+                .map(|weights| vec_to_f64(weights))
+                .collect(),
+            step: self.step,
+            last_updated: self.last_updated.clone(),
+            visit_counts: self.visit_counts.clone(),
+            weight_decay: to_f64(self.forgetting.weight_decay),
+            context_reset_steps: self.forgetting.context_reset_steps,
+        }
+    }
+
+    pub fn from_state(state: &GateState) -> Self {
         Gate {
-            weights: weight_init_func(input_dim, context_dim),
-            context_func: HalfSpaceContext::new(context_dim, feature_dim),
+            weights: state.weights.iter().map(|weights| vec_from_f64(weights)).collect(),
+            context_func: HalfSpaceContext::from_state(&state.context),
+            initial_weights: state
+                .initial_weights
+                .iter()
+                .map(|weights| vec_from_f64(weights))
+                .collect(),
+            step: state.step,
+            last_updated: state.last_updated.clone(),
+            visit_counts: state.visit_counts.clone(),
+            forgetting: ForgettingConfig {
+                weight_decay: from_f64(state.weight_decay),
+                context_reset_steps: state.context_reset_steps,
+            },
+            adaptation: None,
         }
     }
 }
 
-impl<C: ContextFunction> Gate<C> {
-    pub fn select_weights(&self, side_info: &DVector<f32>) -> (Vec<f32>, usize) {
-        let indicator = Self::transform_contexts_to_weight_indicator(
-            self.context_func.indicator_func(side_info.as_slice()),
-        );
-        (self.weights[indicator].clone(), indicator)
+impl<C: ContextFunction<T>, T: GLNFloat> Gate<C, T> {
+    // Sizes the weight table from the number of bits of `context_func`.
+    pub fn from_context_func<F>(input_dim: usize, context_func: C, weight_init_func: F) -> Self
+        where
+            F: FnOnce(usize, usize) -> Vec<Vec<T>>,
+    {
+        let weights = weight_init_func(input_dim, context_func.context_dim());
+        Gate::with_context_func(weights, context_func)
+    }
+
+    pub fn with_context_func(weights: Vec<Vec<T>>, context_func: C) -> Self {
+        let num_contexts = weights.len();
+        Gate {
+            initial_weights: weights.clone(),
+            weights,
+            context_func,
+            step: 0,
+            last_updated: vec![0; num_contexts],
+            visit_counts: vec![0; num_contexts],
+            forgetting: ForgettingConfig::disabled(),
+            adaptation: None,
+        }
+    }
+
+    pub fn input_dim(&self) -> usize {
+        self.weights[0].len()
+    }
+
+    pub fn set_context_adaptation(&mut self, adaptation: Option<ContextAdaptation<T>>) {
+        self.adaptation = adaptation;
     }
 
-    pub fn update_weights(&mut self, context_index: usize, weights: Vec<f32>) {
-        self.weights[context_index] = weights;
+    // Moves the context boundaries by the adaptation statistics of one example. A moved
+    // boundary changes the regions on both of its sides, so every context is reset. Returns
+    // the bits of the moved boundaries, which are 0 when the context did not change.
+    pub fn adapt_context(&mut self, side_info: &DVector<T>) -> ContextIndex {
+        let changed_bits = match self.adaptation.as_mut() {
+            Some(adaptation) => self.context_func.adapt(adaptation, side_info.as_slice()),
+            None => 0,
+        };
+        if changed_bits != 0 {
+            self.reset_weights();
+        }
+        changed_bits
     }
 
-    fn transform_contexts_to_weight_indicator(contexts: Vec<bool>) -> usize {
-        let mut weight_indicator: i32 = 0;
-        // Transform a binary number context to a decimal number.
-        // This decimal number specifies the index of the weight to be used.
-        for (index, bit) in contexts.iter().enumerate() {
-            weight_indicator += 2_i32.pow(index as u32) * (*bit as i32)
+    // Replaces the weights and the initialization that forgetting shrinks toward, and
+    // forgets how often each context was trained.
+    pub fn reinitialize_weights(&mut self, weights: Vec<Vec<T>>) {
+        self.initial_weights = weights;
+        self.reset_weights();
+    }
+
+    // Applies `constraint` to every weight and initial weight, given the index of its input.
+    pub fn constrain_weights<F: Fn(usize, T) -> T>(&mut self, constraint: F) {
+        for weights in self.weights.iter_mut().chain(self.initial_weights.iter_mut()) {
+            for (input_index, weight) in weights.iter_mut().enumerate() {
+                *weight = constraint(input_index, *weight);
+            }
         }
-        weight_indicator as usize
     }
 
-    pub fn get_weights(&self, context_index: ContextIndex) -> Vec<f32> {
-        self.weights[context_index].clone()
+    pub fn set_forgetting(&mut self, forgetting: ForgettingConfig<T>) {
+        self.forgetting = forgetting;
+    }
+
+    pub fn context_func(&self) -> &C {
+        &self.context_func
+    }
+
+    pub fn set_context_func(&mut self, context_func: C) {
+        self.context_func = context_func;
+    }
+
+    pub fn context_bits(&self, side_info: &DVector<T>) -> Vec<bool> {
+        self.context_func.indicator_func(side_info.as_slice())
+    }
+
+    pub fn select_weights(&self, side_info: &DVector<T>) -> (Vec<T>, usize) {
+        let indicator = self.select_context_index(side_info);
+        (self.get_weights(indicator), indicator)
+    }
+
+    pub fn select_context_index(&self, side_info: &DVector<T>) -> ContextIndex {
+        self.context_func.context_index(side_info.as_slice())
+    }
+
+    pub fn update_weights(&mut self, context_index: usize, weights: Vec<T>) {
+        self.update_weights_from_slice(context_index, &weights);
+    }
+
+    // Overwrites the stored weights in place, so that updates do not allocate.
+    pub fn update_weights_from_slice(&mut self, context_index: ContextIndex, weights: &[T]) {
+        // A context that was reset for inactivity starts counting its visits again.
+        self.visit_counts[context_index] = self.get_visit_count(context_index) + 1;
+        self.step += 1;
+        self.last_updated[context_index] = self.step;
+        self.weights[context_index].copy_from_slice(weights);
+        if self.forgetting.weight_decay > T::zero() {
+            // The update counts as a step for this context as well, so it is shrunk once here.
+            let factor = T::one() - self.forgetting.weight_decay;
+            for (weight, initial) in self.weights[context_index]
+                .iter_mut()
+                .zip(&self.initial_weights[context_index])
+            {
+                *weight = *initial + (*weight - *initial) * factor;
+            }
+        }
+    }
+
+    // Moves every context's weights toward their initialization, where `rate` 1 is a full reset.
+    // The visit counts shrink by the same rate, so uncertainty grows with the forgotten evidence.
+    pub fn shrink_to_initial_weights(&mut self, rate: T) {
+        let factor = T::one() - rate;
+        let count_factor: f64 = factor.max(T::zero()).to_subset().unwrap();
+        for context_index in 0..self.weights.len() {
+            let current_weights = self.get_weights(context_index);
+            self.weights[context_index] = self.shrink(context_index, current_weights, factor);
+            self.visit_counts[context_index] =
+                (self.get_visit_count(context_index) as f64 * count_factor).round() as usize;
+            self.last_updated[context_index] = self.step;
+        }
+    }
+
+    // Restores every context's initial weights and forgets how often they were trained.
+    pub fn reset_weights(&mut self) {
+        self.weights.clone_from(&self.initial_weights);
+        for context_index in 0..self.weights.len() {
+            self.last_updated[context_index] = self.step;
+            self.visit_counts[context_index] = 0;
+        }
+    }
+
+    fn shrink(&self, context_index: ContextIndex, weights: Vec<T>, factor: T) -> Vec<T> {
+        weights
+            .iter()
+            .zip(&self.initial_weights[context_index])
+            .map(|(weight, initial)| *initial + (*weight - *initial) * factor)
+            .collect()
+    }
+
+    // Zero for a context that was reset for inactivity.
+    pub fn get_visit_count(&self, context_index: ContextIndex) -> usize {
+        if self.is_reset_for_inactivity(context_index) {
+            0
+        } else {
+            self.visit_counts[context_index]
+        }
+    }
+
+    fn is_reset_for_inactivity(&self, context_index: ContextIndex) -> bool {
+        match self.forgetting.context_reset_steps {
+            Some(reset_steps) => self.step - self.last_updated[context_index] > reset_steps,
+            None => false,
+        }
+    }
+
+    pub fn get_weights(&self, context_index: ContextIndex) -> Vec<T> {
+        self.weights(context_index).into_owned()
+    }
+
+    // Borrows the stored weights unless forgetting has to be applied to them first.
+    pub fn weights(&self, context_index: ContextIndex) -> Cow<'_, [T]> {
+        if self.is_reset_for_inactivity(context_index) {
+            return Cow::Borrowed(&self.initial_weights[context_index]);
+        }
+        let elapsed = self.step - self.last_updated[context_index];
+        if self.forgetting.weight_decay > T::zero() && elapsed > 0 {
+            let factor = (T::one() - self.forgetting.weight_decay).powi(elapsed as i32);
+            Cow::Owned(self.shrink(context_index, self.weights[context_index].clone(), factor))
+        } else {
+            Cow::Borrowed(&self.weights[context_index])
+        }
+    }
+}
+
+impl<C: ContextFunction<T> + Clone + PartialEq, T: GLNFloat> Gate<C, T> {
+    // Gates can be merged when they split the side information identically and
+    // have weight tables of the same shape.
+    pub fn is_mergeable_with(&self, other: &Gate<C, T>) -> bool {
+        self.context_func == other.context_func
+            && self.weights.len() == other.weights.len()
+            && self.weights[0].len() == other.weights[0].len()
+    }
+
+    // Averages the weights of every context, weighted by how often each gate trained it.
+    // Contexts no gate trained keep the first gate's weights after forgetting, since the
+    // merged gate starts counting steps again.
+    pub fn merge(gates: &[&Gate<C, T>]) -> Gate<C, T> {
+        let mut merged = Gate::with_context_func(
+            (0..gates[0].weights.len())
+                .map(|context_index| gates[0].get_weights(context_index))
+                .collect(),
+            gates[0].context_func.clone(),
+        );
+        merged.initial_weights = gates[0].initial_weights.clone();
+        merged.forgetting = gates[0].forgetting;
+        merged.adaptation = gates[0].adaptation.clone();
+
+        for context_index in 0..merged.weights.len() {
+            let visit_count: usize = gates
+                .iter()
+                .map(|gate| gate.get_visit_count(context_index))
+                .sum();
+            merged.visit_counts[context_index] = visit_count;
+            if visit_count == 0 {
+                continue;
+            }
+
+            let mut weights = vec![T::zero(); merged.weights[context_index].len()];
+            for gate in gates.iter() {
+                let share: T = convert(
+                    gate.get_visit_count(context_index) as f64 / visit_count as f64,
+                );
+                for (weight, gate_weight) in weights.iter_mut().zip(gate.get_weights(context_index)) {
+                    *weight += share * gate_weight;
+                }
+            }
+            merged.weights[context_index] = weights;
+        }
+        merged
     }
 }
 
-pub fn initialize_balanced_weights(input_dim: usize, context_dim: usize) -> Vec<Vec<f32>> {
-    let init_value: f32 = 1.0 / (input_dim as f32);
+pub fn initialize_balanced_weights<T: GLNFloat>(input_dim: usize, context_dim: usize) -> Vec<Vec<T>> {
+    let init_value: T = T::one() / convert(input_dim as f64);
     (0..2_i32.pow(context_dim as u32))
         .into_iter()
         .map(|_| {
             (0..input_dim)
                 .into_iter()
                 .map(|_| init_value)
-                .collect::<Vec<f32>>()
+                .collect::<Vec<T>>()
+        })
+        .collect()
+}
+
+pub fn initialize_zero_weights<T: GLNFloat>(input_dim: usize, context_dim: usize) -> Vec<Vec<T>> {
+    vec![vec![T::zero(); input_dim]; 1 << context_dim]
+}
+
+// Samples every weight uniformly from [low, high).
+pub fn initialize_uniform_weights<T: GLNFloat, R: Rng>(
+    input_dim: usize,
+    context_dim: usize,
+    low: T,
+    high: T,
+    rng: &mut R,
+) -> Vec<Vec<T>> {
+    let (low, high): (f64, f64) = (low.to_subset().unwrap(), high.to_subset().unwrap());
+    (0..1 << context_dim)
+        .map(|_| (0..input_dim).map(|_| convert(rng.gen_range(low..high))).collect())
+        .collect()
+}
+
+// Passes the input at `input_index` through unchanged, since a mixture of logits with a
+// single unit weight is that logit.
+pub fn initialize_identity_weights<T: GLNFloat>(
+    input_dim: usize,
+    context_dim: usize,
+    input_index: usize,
+) -> Vec<Vec<T>> {
+    assert!(input_index < input_dim, "input index {} is out of range for {} inputs", input_index, input_dim);
+    let mut weights = initialize_zero_weights(input_dim, context_dim);
+    for context_weights in weights.iter_mut() {
+        context_weights[input_index] = T::one();
+    }
+    weights
+}
+
+// Warm-starts from the trained weights of `source`, whose context function has a different
+// number of bits but agrees with the new one on the low bits, e.g. the same first hyperplanes.
+// A context copies the source context with the same low bits, or with fewer bits the
+// average of the source contexts it merges, weighted by how often each was trained.
+pub fn initialize_from_gate<C: ContextFunction<T>, T: GLNFloat>(
+    source: &Gate<C, T>,
+    input_dim: usize,
+    context_dim: usize,
+) -> Vec<Vec<T>> {
+    assert_eq!(source.input_dim(), input_dim, "the source gate has a different number of inputs");
+    let num_contexts = 1 << context_dim;
+    let num_source_contexts = source.weights.len();
+    if num_contexts >= num_source_contexts {
+        return (0..num_contexts)
+            .map(|context_index| source.get_weights(context_index % num_source_contexts))
+            .collect();
+    }
+
+    (0..num_contexts)
+        .map(|context_index| {
+            let source_indices: Vec<ContextIndex> = (context_index..num_source_contexts).step_by(num_contexts).collect();
+            let visit_count: usize = source_indices.iter().map(|index| source.get_visit_count(*index)).sum();
+            let mut weights = vec![T::zero(); input_dim];
+            for source_index in source_indices.iter() {
+                let share: T = if visit_count == 0 {
+                    convert(1.0 / source_indices.len() as f64)
+                } else {
+                    convert(source.get_visit_count(*source_index) as f64 / visit_count as f64)
+                };
+                for (weight, source_weight) in weights.iter_mut().zip(source.weights(*source_index).iter()) {
+                    *weight += share * *source_weight;
+                }
+            }
+            weights
         })
         .collect()
 }
@@ -71,25 +397,37 @@ mod test {
     use mockall::mock;
     use nalgebra::DVector;
 
+    use crate::model::adaptation::ContextAdaptationConfig;
+    use crate::model::config::ForgettingConfig;
     use crate::model::context_func::ContextFunction;
-    use crate::model::context_func::HalfSpaceContext;
-    use crate::model::gate::{Gate, initialize_balanced_weights};
-
-    #[test]
-    fn test_transform_bits_to_weight_indicator() {
-        let contexts = vec![true, false, true, true];
-        let actual = Gate::<HalfSpaceContext>::transform_contexts_to_weight_indicator(contexts);
-        assert_eq!(actual, 13);
-    }
+    use crate::model::context_func::{CategoricalContext, ConcatContext, FixedContext, HalfSpaceContext};
+    use crate::model::gate::{
+        Gate, initialize_balanced_weights, initialize_from_gate, initialize_identity_weights,
+        initialize_uniform_weights, initialize_zero_weights,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
 
     mock! {
         pub ContextFunctionM {}
 
         impl ContextFunction for ContextFunctionM {
             fn indicator_func(&self, side_info: &[f32]) -> Vec<bool>;
+            fn context_dim(&self) -> usize;
         }
     }
 
+    #[test]
+    fn test_transform_bits_to_weight_indicator() {
+        let mut mock_context_func = MockContextFunctionM::new();
+        mock_context_func
+            .expect_indicator_func()
+            .returning(|_| vec![true, false, true, true]);
+        let gate = Gate::with_context_func(vec![vec![0.5]; 16], mock_context_func);
+        let actual = gate.select_context_index(&DVector::from_vec(vec![0.1]));
+        assert_eq!(actual, 13);
+    }
+
     #[test]
     fn test_select_weights() {
         let mut mock_context_func = MockContextFunctionM::new();
@@ -97,15 +435,15 @@ mod test {
             .expect_indicator_func()
             .times(1)
             .returning(|side_info| vec![false, true]);
-        let gate = Gate {
-            weights: vec![
+        let gate = Gate::with_context_func(
+            vec![
                 vec![0.1, 0.2],
                 vec![0.3, 0.4],
                 vec![0.5, 0.6],
                 vec![0.7, 0.8],
             ],
-            context_func: mock_context_func,
-        };
+            mock_context_func,
+        );
 
         let side_info = vec![0.1, 0.2, 0.2, 0.9];
         let side_info_vec = DVector::from_vec(side_info);
@@ -122,11 +460,212 @@ mod test {
         let actual = &gate.weights[0];
         let expected: Vec<f32> = vec![0.2, 0.1];
         assert_eq!(*actual, expected);
+        assert_eq!(gate.get_visit_count(0), 1);
+        assert_eq!(gate.get_visit_count(1), 0);
+    }
+
+    #[test]
+    fn test_weight_decay_shrinks_toward_initial_weights() {
+        let mut gate = Gate::<HalfSpaceContext, f32>::new(2, 1, 10, initialize_balanced_weights);
+        gate.set_forgetting(ForgettingConfig {
+            weight_decay: 0.5,
+            context_reset_steps: None,
+        });
+        gate.update_weights(0, vec![1.5, 0.5]);
+        assert_eq!(gate.get_weights(0), vec![1.0, 0.5]);
+
+        gate.update_weights(1, vec![0.5, 0.5]);
+        assert_eq!(gate.get_weights(0), vec![0.75, 0.5]);
+    }
+
+    #[test]
+    fn test_context_reset_after_inactivity() {
+        let mut gate = Gate::<HalfSpaceContext, f32>::new(2, 1, 10, initialize_balanced_weights);
+        gate.set_forgetting(ForgettingConfig {
+            weight_decay: 0.0,
+            context_reset_steps: Some(2),
+        });
+        gate.update_weights(0, vec![0.9, 0.1]);
+        gate.update_weights(1, vec![0.2, 0.2]);
+        gate.update_weights(1, vec![0.2, 0.2]);
+        assert_eq!(gate.get_weights(0), vec![0.9, 0.1]);
+
+        gate.update_weights(1, vec![0.2, 0.2]);
+        assert_eq!(gate.get_weights(0), vec![0.5, 0.5]);
+        assert_eq!(gate.get_visit_count(0), 0);
+        assert_eq!(gate.get_visit_count(1), 3);
+
+        gate.update_weights(0, vec![0.6, 0.4]);
+        assert_eq!(gate.get_visit_count(0), 1);
+    }
+
+    #[test]
+    fn test_shrink_to_initial_weights() {
+        let mut gate = Gate::<HalfSpaceContext, f32>::new(2, 1, 10, initialize_balanced_weights);
+        gate.update_weights(0, vec![1.5, 0.5]);
+        gate.update_weights(0, vec![1.5, 0.5]);
+        gate.shrink_to_initial_weights(0.5);
+        assert_eq!(gate.get_visit_count(0), 1);
+
+        gate.shrink_to_initial_weights(1.0);
+        assert_eq!(gate.get_weights(0), vec![0.5, 0.5]);
+        assert_eq!(gate.get_visit_count(0), 0);
+    }
+
+    #[test]
+    fn test_merge_weights_by_visit_count() {
+        let mut first = Gate::<HalfSpaceContext, f32>::new(2, 1, 10, initialize_balanced_weights);
+        let mut second = first.clone();
+        first.update_weights(0, vec![0.2, 0.8]);
+        second.update_weights(0, vec![0.8, 0.2]);
+        second.update_weights(0, vec![0.8, 0.2]);
+        second.update_weights(0, vec![0.8, 0.2]);
+
+        assert!(first.is_mergeable_with(&second));
+        let merged = Gate::merge(&[&first, &second]);
+        let merged_weights = merged.get_weights(0);
+        assert!((merged_weights[0] - 0.65).abs() < 1e-6);
+        assert!((merged_weights[1] - 0.35).abs() < 1e-6);
+        assert_eq!(merged.get_weights(1), vec![0.5, 0.5]);
+        assert_eq!(merged.get_visit_count(0), 4);
+
+        let other = Gate::<HalfSpaceContext, f32>::new(2, 1, 10, initialize_balanced_weights);
+        assert!(!first.is_mergeable_with(&other));
+    }
+
+    #[test]
+    fn test_merge_keeps_contexts_reset_for_inactivity() {
+        let mut first = Gate::<HalfSpaceContext, f32>::new(2, 1, 10, initialize_balanced_weights);
+        first.set_forgetting(ForgettingConfig {
+            weight_decay: 0.0,
+            context_reset_steps: Some(2),
+        });
+        let second = first.clone();
+        first.update_weights(0, vec![0.9, 0.1]);
+        for _ in 0..3 {
+            first.update_weights(1, vec![0.2, 0.2]);
+        }
+        assert_eq!(first.get_visit_count(0), 0);
+
+        let merged = Gate::merge(&[&first, &second]);
+        assert_eq!(merged.get_weights(0), vec![0.5, 0.5]);
+        assert_eq!(merged.get_weights(1), vec![0.2, 0.2]);
+    }
+
+    #[test]
+    fn test_adapt_context_redraws_imbalanced_hyperplane() {
+        let mut gate = Gate::<HalfSpaceContext<f64>, f64>::new(2, 2, 3, initialize_balanced_weights);
+        let config = ContextAdaptationConfig::RedrawImbalanced {
+            max_imbalance: 0.9,
+            min_samples: 20,
+        };
+        gate.set_context_adaptation(config.build(2, 1));
+        let before = gate.context_func().clone();
+        let side_info = DVector::from_vec(vec![50.0, 50.0, 50.0]);
+        gate.update_weights(0, vec![0.8, 0.2]);
+        gate.update_weights(1, vec![0.9, 0.1]);
+
+        let changes: Vec<_> = (0..20).map(|_| gate.adapt_context(&side_info)).filter(|bits| *bits != 0).collect();
+        assert_eq!(changes, vec![0b11]);
+        assert!(before != *gate.context_func());
+        // The regions on both sides of the redrawn hyperplanes changed, so every context is reset.
+        for context_index in 0..4 {
+            assert_eq!(gate.get_weights(context_index), vec![0.5, 0.5]);
+            assert_eq!(gate.get_visit_count(context_index), 0);
+        }
+        // The redrawn hyperplanes pass through the example that triggered them.
+        for (projection, bias) in gate.context_func().projections(side_info.as_slice()).iter().zip(gate.context_func().biases()) {
+            assert!((projection - bias).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_adapt_context_is_disabled_by_default() {
+        let mut gate = Gate::<HalfSpaceContext, f32>::new(2, 2, 3, initialize_balanced_weights);
+        let before = gate.context_func().clone();
+        assert_eq!(gate.adapt_context(&DVector::from_vec(vec![50.0, 50.0, 50.0])), 0);
+        assert!(before == *gate.context_func());
+    }
+
+    #[test]
+    fn test_gate_with_categorical_and_half_space_context() {
+        // The half-spaces split the numeric features only.
+        let mut half_space = HalfSpaceContext::<f32>::new(3, 3);
+        half_space.mask_features(&[0]);
+        let context = ConcatContext::new(CategoricalContext::new(vec![0], 2, 0), half_space);
+        let mut gate = Gate::from_context_func(2, context, initialize_balanced_weights);
+        let side_info = DVector::from_vec(vec![31.0, 0.4, -1.2]);
+        let (_, context_index) = gate.select_weights(&side_info);
+        gate.update_weights(context_index, vec![0.9, 0.1]);
+
+        // Another country with the same numeric features only shares the weights on a collision.
+        let other_country = DVector::from_vec(vec![32.0, 0.4, -1.2]);
+        let (weights, other_index) = gate.select_weights(&other_country);
+        assert_eq!(other_index >> 2, context_index >> 2);
+        if other_index != context_index {
+            assert_eq!(weights, vec![0.5, 0.5]);
+        }
+    }
+
+    #[test]
+    fn test_gate_sizes_weights_from_concatenated_bits() {
+        let context = ConcatContext::new(
+            HalfSpaceContext::<f32>::new(2, 3),
+            ConcatContext::new(CategoricalContext::new(vec![0], 3, 0), FixedContext),
+        );
+        let gate = Gate::from_context_func(4, context, initialize_balanced_weights);
+        assert_eq!(gate.weights.len(), 32);
+
+        let gate = Gate::<FixedContext, f32>::from_context_func(4, FixedContext, initialize_balanced_weights);
+        assert_eq!(gate.weights.len(), 1);
+        assert_eq!(gate.select_weights(&DVector::from_vec(vec![3.0, -1.0])), (vec![0.25; 4], 0));
+    }
+
+    #[test]
+    fn test_weight_initializers() {
+        let zero = initialize_zero_weights::<f32>(3, 2);
+        assert_eq!(zero, vec![vec![0.0; 3]; 4]);
+
+        let identity = initialize_identity_weights::<f32>(3, 1, 2);
+        assert_eq!(identity, vec![vec![0.0, 0.0, 1.0]; 2]);
+
+        let uniform = initialize_uniform_weights::<f64, _>(3, 2, -0.5, 0.5, &mut ChaCha8Rng::seed_from_u64(1));
+        assert_eq!(uniform.len(), 4);
+        assert!(uniform.iter().flatten().all(|weight| (-0.5..0.5).contains(weight)));
+        assert_eq!(uniform, initialize_uniform_weights(3, 2, -0.5, 0.5, &mut ChaCha8Rng::seed_from_u64(1)));
+    }
+
+    #[test]
+    fn test_initialize_from_gate_with_a_different_context_dim() {
+        let mut source = Gate::<HalfSpaceContext, f32>::new(2, 2, 3, initialize_balanced_weights);
+        source.update_weights(1, vec![0.9, 0.1]);
+        source.update_weights(3, vec![0.1, 0.3]);
+        source.update_weights(3, vec![0.1, 0.3]);
+        source.update_weights(3, vec![0.1, 0.3]);
+
+        let larger = initialize_from_gate(&source, 2, 3);
+        assert_eq!(larger.len(), 8);
+        assert_eq!(larger[1], vec![0.9, 0.1]);
+        assert_eq!(larger[7], vec![0.1, 0.3]);
+        assert_eq!(larger[4], vec![0.5, 0.5]);
+
+        // Context 1 merges the source contexts 1 and 3, and context 0 the untrained 0 and 2.
+        let smaller = initialize_from_gate(&source, 2, 1);
+        assert_eq!(smaller[0], vec![0.5, 0.5]);
+        assert!((smaller[1][0] - 0.3).abs() < 1e-6);
+        assert!((smaller[1][1] - 0.25).abs() < 1e-6);
+
+        let mut gate = Gate::<HalfSpaceContext, f32>::new(2, 1, 3, initialize_balanced_weights);
+        gate.update_weights(0, vec![2.0, 2.0]);
+        gate.reinitialize_weights(smaller);
+        assert_eq!(gate.get_visit_count(0), 0);
+        gate.shrink_to_initial_weights(1.0);
+        assert!((gate.get_weights(1)[1] - 0.25).abs() < 1e-6);
     }
 
     #[test]
     fn test_initialize_balanced_weights() {
-        let actual = initialize_balanced_weights(2, 2);
+        let actual = initialize_balanced_weights::<f32>(2, 2);
         let expected: Vec<Vec<f32>> = vec![
             vec![0.5, 0.5],
             vec![0.5, 0.5],
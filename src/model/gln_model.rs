@@ -1,120 +1,568 @@
-use nalgebra::DVector;
+use nalgebra::{convert, DMatrix, DVector};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
-use crate::model::config::LayerConfig;
+use crate::model::adaptation::ContextAdaptationConfig;
+use crate::model::calibration::{CalibrationConfig, Calibrator};
+use crate::model::config::{
+    ClassWeights, ContextSharing, ForgettingConfig, LayerConfig, Monotonicity,
+    WeightInitialization, WeightProjection,
+};
+use crate::model::context_func::{ContextFunction, HalfSpaceContext, HyperplaneSampling};
+use crate::model::drift::{DriftDetector, DriftDetectorConfig};
 use crate::model::layer::{BaseLayer, Layer};
+use crate::model::neuron::Neuron;
+use crate::model::observer::{ExampleTrainEvent, TrainObserver};
+use crate::model::persistence::GLNState;
 use crate::utils::data_type::{ContextIndex, LayerId, NeuronId};
-use crate::utils::math::{calibration, geometric_mixing_loss, sigmoid};
+use crate::utils::float::GLNFloat;
+use crate::utils::math::{geometric_mixing_logit_loss, geometric_mixing_loss, sigmoid};
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
-pub struct GLN {
-    layers: Vec<Layer>,
-    base_layer: BaseLayer,
+pub struct GLN<T: GLNFloat = f32, C: ContextFunction<T> = HalfSpaceContext<T>> {
+    layers: Vec<Layer<T, C>>,
+    base_layer: BaseLayer<T>,
     num_layers: usize,
-    negative_weight: f32,
+    calibration_config: CalibrationConfig<T>,
+    calibrator: Box<dyn Calibrator<T> + Send + Sync>,
+    drift_detector_config: Option<DriftDetectorConfig<T>>,
+    drift_detector: Option<Box<dyn DriftDetector<T> + Send + Sync>>,
+    drift_reset_rate: T,
+    // Set when any gate adapts its context, so the default training path skips adaptation.
+    adapts_contexts: bool,
+    observers: Vec<Box<dyn TrainObserver<T> + Send + Sync>>,
 }
 
-pub struct GLNPrediction {
-    pub probability: f32,
-    pub context_index_map: HashMap<LayerId, HashMap<NeuronId, ContextIndex>>,
+// Observers are not cloned, so snapshots and copies of a model train silently.
+impl<T: GLNFloat, C: ContextFunction<T> + Clone> Clone for GLN<T, C> {
+    fn clone(&self) -> Self {
+        GLN {
+            layers: self.layers.clone(),
+            base_layer: self.base_layer.clone(),
+            num_layers: self.num_layers,
+            calibration_config: self.calibration_config.clone(),
+            calibrator: self.calibrator.clone_box(),
+            drift_detector_config: self.drift_detector_config.clone(),
+            drift_detector: self.drift_detector.as_ref().map(|detector| detector.clone_box()),
+            drift_reset_rate: self.drift_reset_rate,
+            adapts_contexts: self.adapts_contexts,
+            observers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MergeError {
+    NoModels,
+    LayerCountMismatch,
+    NeuronCountMismatch { layer_id: LayerId },
+    // Hyperplanes or weight table shapes differ, e.g. the models were not built with the same seed.
+    NeuronMismatch { layer_id: LayerId, neuron_id: NeuronId },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::NoModels => write!(f, "no models to merge"),
+            MergeError::LayerCountMismatch => write!(f, "models have different numbers of layers"),
+            MergeError::NeuronCountMismatch { layer_id } => {
+                write!(f, "layer {} has different numbers of neurons", layer_id)
+            }
+            MergeError::NeuronMismatch {
+                layer_id,
+                neuron_id,
+            } => write!(
+                f,
+                "neuron {} of layer {} has different contexts or weight shapes",
+                neuron_id, layer_id
+            ),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+pub struct GLNPrediction<T: GLNFloat = f32> {
+    pub probability: T,
+    pub raw_probability: T,
+    pub context_index_map: Vec<Vec<ContextIndex>>,
+    pub uncertainty: Option<PredictionUncertainty<T>>,
+}
+
+pub struct PredictionUncertainty<T: GLNFloat = f32> {
+    // Mean of 1 / sqrt(visit count + 1) over the selected contexts of each layer's neurons.
+    pub layer_uncertainties: Vec<T>,
+    pub aggregate: T,
+    // Standard deviation of the probabilities of the penultimate layer's neurons.
+    pub disagreement: T,
+}
+
+// Once the contexts are fixed the network is linear in logit space, so the output logit is
+// exactly the sum of `contributions`, one per base input.
+pub struct GLNExplanation<T: GLNFloat = f32> {
+    pub output_logit: T,
+    pub base_logits: Vec<T>,
+    pub contributions: Vec<T>,
+    pub context_index_map: Vec<Vec<ContextIndex>>,
+    // Which half-space hyperplanes of each neuron were on the positive side.
+    pub context_bits: HashMap<LayerId, HashMap<NeuronId, Vec<bool>>>,
+}
+
+// Buffers reused by `GLN::predict_fit_with_workspace`.
+pub struct GLNWorkspace<T: GLNFloat = f32> {
+    // Context index selected by each neuron, by layer.
+    context_indices: Vec<Vec<ContextIndex>>,
+    // Base logits followed by the outputs of every layer.
+    logits: Vec<Vec<T>>,
+    // Updated weights of the neuron being trained.
+    weights: Vec<T>,
+    // Hyperplane projections of the layer whose contexts are being selected.
+    projections: DVector<T>,
+}
+
+impl<T: GLNFloat> GLNWorkspace<T> {
+    pub fn context_indices(&self) -> &[Vec<ContextIndex>] {
+        &self.context_indices
+    }
 }
 
-pub struct GLNTrainHistory {
-    pub loss_histories: HashMap<LayerId, HashMap<NeuronId, f32>>,
+pub struct GLNTrainHistory<T: GLNFloat = f32> {
+    pub loss_histories: HashMap<LayerId, HashMap<NeuronId, T>>,
+    pub drift_detected: bool,
 }
 
-pub struct PredictFitResult {
-    pub prediction: f32,
-    pub loss_histories: HashMap<LayerId, HashMap<NeuronId, f32>>,
+pub struct PredictFitResult<T: GLNFloat = f32> {
+    pub prediction: T,
+    pub loss_histories: HashMap<LayerId, HashMap<NeuronId, T>>,
+    pub drift_detected: bool,
 }
 
-impl GLN {
+impl<T: GLNFloat> GLN<T> {
     pub fn new(
         neuron_nums: Vec<usize>,
         context_dim: usize,
         feature_dim: usize,
-        learning_rate: f32,
-        weight_clipping_value: f32,
-        negative_weight: f32,
-        reg_param: f32,
+        learning_rate: T,
+        weight_clipping_value: T,
+        negative_weight: T,
+        reg_param: T,
     ) -> Self {
-        let mut layers = Vec::with_capacity(neuron_nums.len());
-        let first_layer = Layer::with_neuron_num(
-            neuron_nums[0],
-            feature_dim,
-            context_dim,
+        GLN::with_context_funcs(
+            neuron_nums,
             feature_dim,
             learning_rate,
             weight_clipping_value,
             negative_weight,
             reg_param,
+            |_, _| HalfSpaceContext::new(context_dim, feature_dim),
+        )
+    }
+
+    pub fn to_state(&self) -> GLNState {
+        GLNState {
+            base_layer: self.base_layer.to_state(),
+            layers: self.layers.iter().map(|layer| layer.to_state()).collect(),
+            calibration: self.calibrator.to_state(),
+        }
+    }
+
+    // Restores the network and its fitted calibrator from `state`, with drift detection disabled.
+    pub fn from_state(state: &GLNState) -> Self {
+        let layers: Vec<Layer<T>> = state.layers.iter().map(Layer::from_state).collect();
+        GLN {
+            num_layers: layers.len(),
+            layers,
+            base_layer: BaseLayer::from_state(&state.base_layer),
+            calibration_config: CalibrationConfig::from_state(&state.calibration),
+            calibrator: CalibrationConfig::build_from_state(&state.calibration),
+            drift_detector_config: None,
+            drift_detector: None,
+            drift_reset_rate: T::zero(),
+            adapts_contexts: false,
+            observers: Vec::new(),
+        }
+    }
+
+    // Resamples all context hyperplanes from `seed`. Models built with the same
+    // architecture and seed share their contexts and can be merged.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for layer in self.layers.iter_mut() {
+            layer.resample_contexts(&mut rng);
+        }
+        self
+    }
+
+    // Resamples all context hyperplanes with `sampling`, placed relative to `calibration_data`
+    // (e.g. the first examples of the stream) and drawn from `seed`.
+    pub fn with_hyperplane_sampling(
+        mut self,
+        sampling: HyperplaneSampling,
+        calibration_data: &[DVector<T>],
+        seed: u64,
+    ) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for layer in self.layers.iter_mut() {
+            layer.sample_contexts(sampling, calibration_data, &mut rng);
+        }
+        self
+    }
+
+    // Starts every gate from the trained weights and hyperplanes of the same gate in `source`,
+    // e.g. a model with a smaller context dimension. Panics if the layer or neuron counts differ.
+    pub fn with_warm_start(mut self, source: &GLN<T>) -> Self {
+        assert_eq!(self.num_layers, source.num_layers, "the source model has a different number of layers");
+        for (layer, source_layer) in self.layers.iter_mut().zip(&source.layers) {
+            layer.warm_start_from(source_layer);
+        }
+        self
+    }
+
+    // Makes the output monotone in the base inputs, given one entry per input. A constrained
+    // input must be a probability, e.g. an upstream model score: it is passed through instead of
+    // being normalized with the other features, no context depends on it, the first layer's
+    // weights on it get its sign and every later layer's weights are non-negative.
+    pub fn with_monotonicity(mut self, monotonicity: Vec<Monotonicity>) -> Self {
+        assert_eq!(
+            monotonicity.len(),
+            self.base_layer.feature_dim(),
+            "monotonicity needs one entry per base input"
         );
-        layers.push(first_layer);
-
-        let num_layers = neuron_nums.len();
-        for layer_index in (0..num_layers).skip(1) {
-            let input_dim = neuron_nums[layer_index - 1] as usize;
-            let layer = Layer::with_neuron_num(
-                neuron_nums[layer_index],
-                input_dim,
-                context_dim,
-                feature_dim,
-                learning_rate,
-                weight_clipping_value,
-                negative_weight,
-                reg_param,
-            );
-            layers.push(layer);
-        }
-
-        let config = LayerConfig::with_default_value();
+        let constrained: Vec<usize> = (0..monotonicity.len())
+            .filter(|index| monotonicity[*index] != Monotonicity::Unconstrained)
+            .collect();
+        self.base_layer
+            .set_monotone_inputs(monotonicity.iter().map(|m| *m != Monotonicity::Unconstrained).collect());
+
+        for (layer_id, layer) in self.layers.iter_mut().enumerate() {
+            let input_monotonicity = if layer_id == 0 {
+                monotonicity.clone()
+            } else if constrained.is_empty() {
+                Vec::new()
+            } else {
+                vec![Monotonicity::Increasing; layer.input_dim()]
+            };
+            layer.set_monotonicity(input_monotonicity, constrained.clone());
+        }
+        self
+    }
+}
+
+impl<T: GLNFloat, C: ContextFunction<T> + Clone> GLN<T, C> {
+    // Builds a network whose neurons use the context function returned by `context_func` for
+    // their layer and neuron id, e.g. a `ConcatContext` of categorical and half-space contexts.
+    pub fn with_context_funcs<F: FnMut(LayerId, NeuronId) -> C>(
+        neuron_nums: Vec<usize>,
+        feature_dim: usize,
+        learning_rate: T,
+        weight_clipping_value: T,
+        negative_weight: T,
+        reg_param: T,
+        mut context_func: F,
+    ) -> Self {
+        let layers: Vec<Layer<T, C>> = neuron_nums
+            .iter()
+            .enumerate()
+            .map(|(layer_id, neuron_num)| {
+                let input_dim = if layer_id == 0 { feature_dim } else { neuron_nums[layer_id - 1] };
+                let neurons = (0..*neuron_num)
+                    .map(|neuron_id| {
+                        Neuron::with_context_func(
+                            input_dim,
+                            context_func(layer_id, neuron_id),
+                            learning_rate,
+                            weight_clipping_value,
+                            negative_weight,
+                            reg_param,
+                        )
+                    })
+                    .collect();
+                Layer::new(neurons, input_dim)
+            })
+            .collect();
+
+        let config = LayerConfig::<T>::with_default_value();
 
         GLN {
+            num_layers: layers.len(),
             layers,
             base_layer: BaseLayer::new(config.pred_clipping_value, feature_dim),
-            num_layers,
-            negative_weight,
+            calibration_config: CalibrationConfig::Disabled,
+            calibrator: CalibrationConfig::Disabled.build(),
+            drift_detector_config: None,
+            drift_detector: None,
+            drift_reset_rate: T::zero(),
+            adapts_contexts: false,
+            observers: Vec::new(),
+        }
+    }
+
+    pub fn with_calibration(mut self, calibration_config: CalibrationConfig<T>) -> Self {
+        self.calibrator = calibration_config.build();
+        self.calibration_config = calibration_config;
+        self
+    }
+
+    // Sets how the neurons of layer `layer_id` share their context functions. Contexts are
+    // independent by default.
+    pub fn with_context_sharing(mut self, layer_id: LayerId, context_sharing: ContextSharing) -> Self {
+        self.layers[layer_id].set_context_sharing(context_sharing);
+        self
+    }
+
+    // Lets every gate move its hyperplanes while training, resetting its weights when they move.
+    // Adaptation is disabled by default and is not saved with the model.
+    pub fn with_context_adaptation(mut self, config: ContextAdaptationConfig<T>, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for layer in self.layers.iter_mut() {
+            layer.set_context_adaptation(config, &mut rng);
+        }
+        self.adapts_contexts = !matches!(config, ContextAdaptationConfig::Disabled);
+        self
+    }
+
+    // Reinitializes the weights of layer `layer_id`, which are balanced by default.
+    pub fn with_weight_initialization(
+        mut self,
+        layer_id: LayerId,
+        initialization: WeightInitialization<T>,
+    ) -> Self {
+        self.layers[layer_id].initialize_weights(initialization);
+        self
+    }
+
+    // Sets the convex set the weights of layer `layer_id` are projected onto after every
+    // update, with the weight clipping value as its radius. The default is the hypercube.
+    pub fn with_weight_projection(mut self, layer_id: LayerId, projection: WeightProjection) -> Self {
+        self.layers[layer_id].set_weight_projection(projection);
+        self
+    }
+
+    pub fn with_forgetting(mut self, forgetting: ForgettingConfig<T>) -> Self {
+        for layer in self.layers.iter_mut() {
+            layer.set_forgetting(forgetting);
         }
+        self
     }
 
-    pub fn predict_fit(&mut self, features: &DVector<f32>, target: i32) -> PredictFitResult {
+    // When the detector fires on the output loss, all weights are moved toward their
+    // initialization by `reset_rate` (1 resets them completely).
+    pub fn with_drift_detection(
+        mut self,
+        detector_config: DriftDetectorConfig<T>,
+        reset_rate: T,
+    ) -> Self {
+        self.drift_detector = Some(detector_config.build());
+        self.drift_detector_config = Some(detector_config);
+        self.drift_reset_rate = reset_rate;
+        self
+    }
+
+    pub fn with_observer(mut self, observer: Box<dyn TrainObserver<T> + Send + Sync>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn with_class_weights(mut self, class_weights: ClassWeights<T>) -> Self {
+        for layer in self.layers.iter_mut() {
+            layer.set_class_weights(class_weights);
+        }
+        self
+    }
+
+    pub fn predict_fit(&mut self, features: &DVector<T>, target: i32) -> PredictFitResult<T> {
+        self.predict_fit_weighted(features, target, T::one())
+    }
+
+    pub fn predict_fit_weighted(
+        &mut self,
+        features: &DVector<T>,
+        target: i32,
+        sample_weight: T,
+    ) -> PredictFitResult<T> {
         let pred = self.predict(features);
-        let train_history = self.train(features, target, &pred.context_index_map);
+        let train_history =
+            self.train_weighted(features, target, sample_weight, &pred.context_index_map);
 
         PredictFitResult {
             prediction: pred.probability,
             loss_histories: train_history.loss_histories,
+            drift_detected: train_history.drift_detected,
         }
     }
 
     pub fn train(
         &mut self,
-        features: &DVector<f32>,
+        features: &DVector<T>,
         target: i32,
-        context_index_map: &HashMap<LayerId, HashMap<NeuronId, ContextIndex>>,
-    ) -> GLNTrainHistory {
-        let mut inputs = self.base_layer.predict(features);
-        let mut loss_history = HashMap::new();
+        context_index_map: &[Vec<ContextIndex>],
+    ) -> GLNTrainHistory<T> {
+        self.train_weighted(features, target, T::one(), context_index_map)
+    }
+
+    // `sample_weight` scales the gradient of every neuron and the reported losses,
+    // e.g. an importance weight from off-policy sampling.
+    pub fn train_weighted(
+        &mut self,
+        features: &DVector<T>,
+        target: i32,
+        sample_weight: T,
+        context_index_map: &[Vec<ContextIndex>],
+    ) -> GLNTrainHistory<T> {
+        let mut workspace = self.workspace();
+        workspace.context_indices = context_index_map.to_vec();
+        let drift_detected = self.train_with_workspace(features, target, sample_weight, &mut workspace);
+
+        let loss_histories = workspace.logits[1..]
+            .iter()
+            .enumerate()
+            .map(|(layer_id, logits)| {
+                let layer_losses = self
+                    .calculate_layer_losses_by_logits(logits, target)
+                    .into_iter()
+                    .map(|(neuron_id, loss)| (neuron_id, sample_weight * loss))
+                    .collect();
+                (layer_id, layer_losses)
+            })
+            .collect();
+
+        GLNTrainHistory {
+            loss_histories,
+            drift_detected,
+        }
+    }
+
+    // Number of features every example must have.
+    pub fn feature_dim(&self) -> usize {
+        self.base_layer.feature_dim()
+    }
+
+    // Buffers sized for this network, to be reused across `predict_fit_with_workspace` calls.
+    pub fn workspace(&self) -> GLNWorkspace<T> {
+        let mut logits = vec![Vec::with_capacity(self.layers[0].input_dim())];
+        logits.extend(self.layers.iter().map(|layer| Vec::with_capacity(layer.num_neurons())));
+        let max_input_dim = self.layers.iter().map(|layer| layer.input_dim()).max().unwrap_or(0);
+
+        GLNWorkspace {
+            context_indices: self
+                .layers
+                .iter()
+                .map(|layer| Vec::with_capacity(layer.num_neurons()))
+                .collect(),
+            logits,
+            weights: Vec::with_capacity(max_input_dim),
+            projections: DVector::zeros(0),
+        }
+    }
+
+    // Same as `predict_fit_weighted` but only returns the prediction, and does not allocate
+    // when forgetting and calibration are disabled and no observer is registered. The context
+    // indices of the example are left in `workspace`.
+    pub fn predict_fit_with_workspace(
+        &mut self,
+        features: &DVector<T>,
+        target: i32,
+        sample_weight: T,
+        workspace: &mut GLNWorkspace<T>,
+    ) -> T {
+        let raw_probability = self.predict_into_workspace(features, workspace);
+        let probability = self.calibrator.calibrate(raw_probability);
+        self.train_with_workspace(features, target, sample_weight, workspace);
+        probability
+    }
+
+    // Trains on the context indices in `workspace`, leaving the pre-update logits of every
+    // layer in it. Returns whether a drift was detected.
+    fn train_with_workspace(
+        &mut self,
+        features: &DVector<T>,
+        target: i32,
+        sample_weight: T,
+        workspace: &mut GLNWorkspace<T>,
+    ) -> bool {
+        self.base_layer.predict_logits_into(features, &mut workspace.logits[0]);
 
         for layer_id in 0usize..self.num_layers {
-            let layer_context_index_map = &context_index_map[&layer_id];
-            let inputs_tmp =
-                self.layers[layer_id].predict_by_context_index(&layer_context_index_map, &inputs);
-            loss_history.insert(layer_id, self.calculate_layer_losses(&inputs_tmp, target));
+            let (inputs, outputs) = workspace.logits.split_at_mut(layer_id + 1);
+            let input_logits = &inputs[layer_id];
+            let context_indices = &workspace.context_indices[layer_id];
+            self.layers[layer_id].predict_logits_into(context_indices, input_logits, &mut outputs[0]);
 
-            self.layers[layer_id].train(&layer_context_index_map, &inputs, target);
-            inputs = inputs_tmp;
+            if self.observers.is_empty() {
+                self.layers[layer_id].train_by_logits_with_scratch(
+                    context_indices,
+                    input_logits,
+                    target,
+                    sample_weight,
+                    &mut workspace.weights,
+                );
+            } else {
+                let layer_history = self.layers[layer_id].train_by_logits_with_history(
+                    context_indices,
+                    input_logits,
+                    target,
+                    sample_weight,
+                );
+                for observer in self.observers.iter_mut() {
+                    for (neuron_id, history) in layer_history.neuron_histories.iter().enumerate() {
+                        observer.on_neuron_trained(layer_id, neuron_id, history);
+                    }
+                    observer.on_layer_trained(layer_id, &layer_history);
+                }
+            }
         }
 
-        GLNTrainHistory {
-            loss_histories: loss_history,
+        if self.adapts_contexts {
+            for layer in self.layers.iter_mut() {
+                layer.adapt_contexts(features);
+            }
         }
+
+        let output_logit = workspace.logits[self.num_layers][0];
+        self.calibrator.update(sigmoid(output_logit), target);
+
+        let output_loss = sample_weight * geometric_mixing_logit_loss(target, output_logit);
+        let drift_detected = self.detect_drift(output_loss);
+
+        if !self.observers.is_empty() {
+            let event = ExampleTrainEvent {
+                target,
+                sample_weight,
+                prediction: sigmoid(output_logit),
+                loss: output_loss,
+                drift_detected,
+            };
+            for observer in self.observers.iter_mut() {
+                observer.on_example_trained(&event);
+            }
+        }
+        drift_detected
+    }
+
+    fn detect_drift(&mut self, output_loss: T) -> bool {
+        let drift_detected = match self.drift_detector.as_mut() {
+            Some(detector) => detector.update(output_loss),
+            None => false,
+        };
+
+        if drift_detected {
+            for layer in self.layers.iter_mut() {
+                layer.shrink_to_initial_weights(self.drift_reset_rate);
+            }
+            if let Some(detector) = self.drift_detector.as_mut() {
+                detector.reset();
+            }
+        }
+        drift_detected
     }
 
     pub fn calculate_layer_losses(
         &self,
-        predictions: &Vec<f32>,
+        predictions: &Vec<T>,
         target: i32,
-    ) -> HashMap<NeuronId, f32> {
+    ) -> HashMap<NeuronId, T> {
         predictions
             .iter()
             .enumerate()
@@ -122,24 +570,193 @@ impl GLN {
             .collect()
     }
 
-    pub fn predict(&self, features: &DVector<f32>) -> GLNPrediction {
-        let mut layer_prediction = self.base_layer.predict_through_logits(features);
-        let mut layer_context_index_map = HashMap::new();
+    pub fn calculate_layer_losses_by_logits(
+        &self,
+        logits: &Vec<T>,
+        target: i32,
+    ) -> HashMap<NeuronId, T> {
+        logits
+            .iter()
+            .enumerate()
+            .map(|(neuron_id, logit)| (neuron_id, geometric_mixing_logit_loss(target, *logit)))
+            .collect()
+    }
 
-        for layer_index in 0..self.num_layers {
-            layer_prediction = self.layers[layer_index]
-                .calculate_next_weight_matrix(features, &layer_prediction.predictions);
-            layer_context_index_map
-                .insert(layer_index, layer_prediction.context_index_map.unwrap());
+    // Number of times the selected context of each neuron has been trained, by layer.
+    pub fn visit_counts(
+        &self,
+        context_index_map: &[Vec<ContextIndex>],
+    ) -> Vec<Vec<usize>> {
+        self.layers
+            .iter()
+            .enumerate()
+            .map(|(layer_id, layer)| layer.visit_counts(&context_index_map[layer_id]))
+            .collect()
+    }
+
+    // Mean visit count over the selected contexts of all neurons.
+    pub fn pseudo_count(
+        &self,
+        context_index_map: &[Vec<ContextIndex>],
+    ) -> T {
+        let visit_counts: Vec<usize> = self
+            .visit_counts(context_index_map)
+            .into_iter()
+            .flatten()
+            .collect();
+        let total: usize = visit_counts.iter().sum();
+        convert::<f64, T>(total as f64) / convert(visit_counts.len() as f64)
+    }
+
+    pub fn predict(&self, features: &DVector<T>) -> GLNPrediction<T> {
+        self.predict_layers(features).0
+    }
+
+    // Same as `predict`, with an uncertainty estimate that grows for contexts which have
+    // rarely been trained and when the neurons of the penultimate layer disagree.
+    pub fn predict_with_uncertainty(&self, features: &DVector<T>) -> GLNPrediction<T> {
+        let (mut prediction, penultimate_predictions) = self.predict_layers(features);
+
+        let layer_uncertainties: Vec<T> = self
+            .visit_counts(&prediction.context_index_map)
+            .iter()
+            .map(|layer_visit_counts| {
+                let total = layer_visit_counts.iter().fold(T::zero(), |acc, count| {
+                    acc + T::one() / convert::<f64, T>(*count as f64 + 1.0).sqrt()
+                });
+                total / convert(layer_visit_counts.len() as f64)
+            })
+            .collect();
+        let aggregate = layer_uncertainties.iter().fold(T::zero(), |acc, u| acc + *u)
+            / convert(layer_uncertainties.len() as f64);
+
+        let probabilities: Vec<T> = penultimate_predictions.iter().map(|pred| sigmoid(*pred)).collect();
+        let num_probabilities: T = convert(probabilities.len() as f64);
+        let mean = probabilities.iter().fold(T::zero(), |acc, p| acc + *p) / num_probabilities;
+        let variance = probabilities
+            .iter()
+            .fold(T::zero(), |acc, p| acc + (*p - mean).powi(2))
+            / num_probabilities;
+
+        prediction.uncertainty = Some(PredictionUncertainty {
+            layer_uncertainties,
+            aggregate,
+            disagreement: variance.sqrt(),
+        });
+        prediction
+    }
+
+    pub fn explain(&self, features: &DVector<T>) -> GLNExplanation<T> {
+        let base_logits = self.base_layer.predict_through_logits(features).predictions;
+        let mut context_index_map = Vec::with_capacity(self.num_layers);
+        let mut context_bits = HashMap::new();
+        // Product of the selected weight matrices so far, mapping base logits to layer outputs.
+        let mut chained_weights = DMatrix::identity(base_logits.nrows(), base_logits.nrows());
+
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            let (weight_matrix, layer_context_index_map) = layer.select_weight_matrix(features);
+            chained_weights = weight_matrix * chained_weights;
+            context_index_map.push(layer_context_index_map);
+            context_bits.insert(layer_id, layer.context_bits(features));
         }
 
-        if let Some(&pred) = layer_prediction.predictions.get(0) {
-            GLNPrediction {
-                probability: calibration(sigmoid(pred), self.negative_weight),
-                context_index_map: layer_context_index_map,
+        let contributions: Vec<T> = base_logits
+            .iter()
+            .enumerate()
+            .map(|(input_id, base_logit)| chained_weights[(0, input_id)] * *base_logit)
+            .collect();
+
+        GLNExplanation {
+            output_logit: (chained_weights * &base_logits)[(0, 0)],
+            base_logits: base_logits.iter().cloned().collect(),
+            contributions,
+            context_index_map,
+            context_bits,
+        }
+    }
+
+    // Returns the prediction together with the outputs (logits) of the penultimate layer,
+    // or of the only layer when there is just one.
+    fn predict_layers(&self, features: &DVector<T>) -> (GLNPrediction<T>, Vec<T>) {
+        let mut workspace = self.workspace();
+        let raw_probability = self.predict_into_workspace(features, &mut workspace);
+        let prediction = GLNPrediction {
+            probability: self.calibrator.calibrate(raw_probability),
+            raw_probability,
+            context_index_map: workspace.context_indices,
+            uncertainty: None,
+        };
+        let penultimate_predictions = workspace.logits.swap_remove(self.num_layers.max(2) - 1);
+        (prediction, penultimate_predictions)
+    }
+
+    // Selects the contexts of every neuron and fills in the logits of every layer, returning
+    // the uncalibrated output probability.
+    fn predict_into_workspace(&self, features: &DVector<T>, workspace: &mut GLNWorkspace<T>) -> T {
+        self.base_layer.predict_through_logits_into(features, &mut workspace.logits[0]);
+
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            let context_indices = &mut workspace.context_indices[layer_id];
+            layer.select_context_indices_into(features, &mut workspace.projections, context_indices);
+            let (inputs, outputs) = workspace.logits.split_at_mut(layer_id + 1);
+            layer.mix_logits_into(context_indices, &inputs[layer_id], &mut outputs[0]);
+        }
+
+        match workspace.logits[self.num_layers].first() {
+            Some(&output_logit) => sigmoid(output_logit),
+            None => panic!("prediction value is not found. `predictions` vector is empty."),
+        }
+    }
+
+}
+
+impl<T: GLNFloat, C: ContextFunction<T> + Clone + PartialEq> GLN<T, C> {
+    pub fn check_merge_compatibility(models: &[GLN<T, C>]) -> Result<(), MergeError> {
+        let first = models.first().ok_or(MergeError::NoModels)?;
+        for model in models.iter().skip(1) {
+            if model.num_layers != first.num_layers {
+                return Err(MergeError::LayerCountMismatch);
+            }
+            for (layer_id, (layer, first_layer)) in model.layers.iter().zip(&first.layers).enumerate() {
+                if layer.num_neurons() != first_layer.num_neurons() {
+                    return Err(MergeError::NeuronCountMismatch { layer_id });
+                }
+                if let Some(neuron_id) = first_layer.find_unmergeable_neuron(layer) {
+                    return Err(MergeError::NeuronMismatch {
+                        layer_id,
+                        neuron_id,
+                    });
+                }
             }
-        } else {
-            panic!("prediction value is not found. `predictions` vector is empty.");
         }
+        Ok(())
+    }
+
+    // Combines models trained on different shards of the data. The weights of each context
+    // are averaged, weighted by how often each model trained that context. The calibration
+    // and drift detection of the first model are used, starting from a fresh state.
+    pub fn merge(models: &[GLN<T, C>]) -> Result<GLN<T, C>, MergeError> {
+        Self::check_merge_compatibility(models)?;
+
+        let first = &models[0];
+        let layers = (0..first.num_layers)
+            .map(|layer_id| {
+                let layers: Vec<_> = models.iter().map(|model| &model.layers[layer_id]).collect();
+                Layer::merge(&layers)
+            })
+            .collect();
+
+        Ok(GLN {
+            layers,
+            base_layer: first.base_layer.clone(),
+            num_layers: first.num_layers,
+            calibration_config: first.calibration_config.clone(),
+            calibrator: first.calibration_config.build(),
+            drift_detector_config: first.drift_detector_config.clone(),
+            drift_detector: first.drift_detector_config.as_ref().map(|config| config.build()),
+            drift_reset_rate: first.drift_reset_rate,
+            adapts_contexts: first.adapts_contexts,
+            observers: Vec::new(),
+        })
     }
 }
@@ -1,48 +1,442 @@
 use std::collections::HashMap;
 
-use nalgebra::{DMatrix, DVector};
-
-use crate::model::context_func::HalfSpaceContext;
-use crate::model::neuron::Neuron;
+use nalgebra::{convert, DMatrix, DVector};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::model::adaptation::ContextAdaptationConfig;
+use crate::model::config::{
+    ClassWeights, ContextSharing, ForgettingConfig, Monotonicity, WeightInitialization,
+    WeightProjection,
+};
+use crate::model::context_func::{ContextFunction, HalfSpaceContext, HyperplaneSampling, LayerContextEvaluator};
+use crate::model::gate::{
+    initialize_balanced_weights, initialize_from_gate, initialize_identity_weights,
+    initialize_uniform_weights, initialize_zero_weights,
+};
+use crate::model::neuron::{Neuron, NeuronTrainHistory};
+use crate::model::persistence::{from_f64, to_f64, BaseLayerState, LayerState};
 use crate::utils::data_type::{ContextIndex, NeuronId};
+use crate::utils::float::GLNFloat;
 use crate::utils::math::{clip_prob, logit};
 
-pub struct LayerPrediction {
-    pub predictions: DMatrix<f32>,
-    pub context_index_map: Option<HashMap<NeuronId, usize>>,
+pub struct LayerPrediction<T: GLNFloat = f32> {
+    pub predictions: DMatrix<T>,
+    pub context_index_map: Option<Vec<ContextIndex>>,
 }
 
-pub struct Layer {
-    neurons: Vec<Neuron<HalfSpaceContext>>,
+#[derive(Clone)]
+pub struct Layer<T: GLNFloat = f32, C: ContextFunction<T> = HalfSpaceContext<T>> {
+    neurons: Vec<Neuron<C, T>>,
     num_neurons: usize,
     input_dim: usize,
+    context_sharing: ContextSharing,
+    // Base inputs the contexts ignore, zeroed again whenever the contexts change.
+    masked_features: Vec<usize>,
+    // Rebuilt whenever the neurons' contexts change. None if `C` has no layer evaluator.
+    context_evaluator: Option<LayerContextEvaluator<T>>,
+}
+
+pub struct LayerTrainHistory<T: GLNFloat = f32> {
+    pub neuron_histories: Vec<NeuronTrainHistory<T>>,
 }
 
-pub struct LayerTrainHistory {
-    neuron_losses: Vec<f32>,
+// Summary of one training step of a layer, for spotting learning that stalls because
+// weights or predictions sit at their clipping values.
+#[derive(Clone, Copy, Debug)]
+pub struct LayerDiagnostics<T: GLNFloat = f32> {
+    pub mean_gradient_norm: T,
+    pub max_gradient_norm: T,
+    // Fractions of all the neurons' weights, predictions and input logits that are clipped.
+    pub saturated_weight_fraction: T,
+    pub clipped_prediction_fraction: T,
+    pub clipped_input_fraction: T,
 }
 
-impl Layer {
-    pub fn new(neurons: Vec<Neuron<HalfSpaceContext>>, input_dim: usize) -> Self {
+impl<T: GLNFloat> LayerTrainHistory<T> {
+    pub fn neuron_losses(&self) -> Vec<T> {
+        self.neuron_histories.iter().map(|history| history.loss).collect()
+    }
+
+    pub fn diagnostics(&self) -> LayerDiagnostics<T> {
+        let histories = &self.neuron_histories;
+        let fraction = |count: usize, total: usize| -> T { convert(count as f64 / total.max(1) as f64) };
+        let gradient_norm_sum = histories
+            .iter()
+            .fold(T::zero(), |acc, history| acc + history.gradient_norm);
+        let num_weights = histories.iter().map(|history| history.weight_delta.len()).sum();
+
+        LayerDiagnostics {
+            mean_gradient_norm: gradient_norm_sum / convert(histories.len().max(1) as f64),
+            max_gradient_norm: histories
+                .iter()
+                .fold(T::zero(), |acc, history| acc.max(history.gradient_norm)),
+            saturated_weight_fraction: fraction(
+                histories.iter().map(|history| history.num_saturated_weights).sum(),
+                num_weights,
+            ),
+            clipped_prediction_fraction: fraction(
+                histories.iter().filter(|history| history.prediction_clipped).count(),
+                histories.len(),
+            ),
+            // Every neuron sees the same inputs, which does not change the fraction.
+            clipped_input_fraction: fraction(
+                histories.iter().map(|history| history.num_clipped_inputs).sum(),
+                num_weights,
+            ),
+        }
+    }
+}
+
+impl<T: GLNFloat, C: ContextFunction<T> + Clone> Layer<T, C> {
+    pub fn new(neurons: Vec<Neuron<C, T>>, input_dim: usize) -> Self {
         let num_neurons = neurons.len();
+        let context_evaluator =
+            Self::build_context_evaluator(&neurons, ContextSharing::Independent);
         Layer {
             neurons,
             num_neurons,
             input_dim,
+            context_sharing: ContextSharing::Independent,
+            masked_features: Vec::new(),
+            context_evaluator,
+        }
+    }
+
+    fn build_context_evaluator(
+        neurons: &[Neuron<C, T>],
+        context_sharing: ContextSharing,
+    ) -> Option<LayerContextEvaluator<T>> {
+        let contexts: Vec<_> = neurons[..Self::num_evaluated(neurons.len(), context_sharing)]
+            .iter()
+            .map(|neuron| neuron.context_func())
+            .collect();
+        C::layer_evaluator(&contexts)
+    }
+
+    // Neurons whose contexts are evaluated, the others reuse the first neuron's index.
+    fn num_evaluated(num_neurons: usize, context_sharing: ContextSharing) -> usize {
+        match context_sharing {
+            ContextSharing::SharedEvaluation => num_neurons.min(1),
+            _ => num_neurons,
+        }
+    }
+
+    // Makes every neuron reference the first neuron's context function unless
+    // `context_sharing` is `Independent`.
+    pub fn set_context_sharing(&mut self, context_sharing: ContextSharing) {
+        self.context_sharing = context_sharing;
+        if context_sharing != ContextSharing::Independent {
+            if let Some(first) = self.neurons.first() {
+                let shared_context = first.context_func().clone();
+                for neuron in self.neurons.iter_mut().skip(1) {
+                    neuron.set_context_func(shared_context.clone());
+                }
+            }
+        }
+        self.context_evaluator = Self::build_context_evaluator(&self.neurons, context_sharing);
+    }
+
+    pub fn context_sharing(&self) -> ContextSharing {
+        self.context_sharing
+    }
+
+    pub fn train(
+        &mut self,
+        context_indices: &[ContextIndex],
+        inputs: &Vec<T>,
+        target: i32,
+    ) {
+        for (neuron, context_index) in self.neurons.iter_mut().zip(context_indices) {
+            neuron.update_weights(inputs, target, *context_index);
+        }
+    }
+
+    pub fn predict_by_context_index(
+        &self,
+        context_indices: &[ContextIndex],
+        inputs: &Vec<T>,
+    ) -> Vec<T> {
+        self.neurons
+            .iter()
+            .zip(context_indices)
+            .map(|(neuron, context_index)| neuron.predict_by_context_index(*context_index, inputs))
+            .collect()
+    }
+
+    pub fn train_by_logits(
+        &mut self,
+        context_indices: &[ContextIndex],
+        input_logits: &[T],
+        target: i32,
+        sample_weight: T,
+    ) {
+        for (neuron, context_index) in self.neurons.iter_mut().zip(context_indices) {
+            neuron.update_weights_by_logits(input_logits, target, *context_index, sample_weight);
         }
     }
 
+    pub fn train_by_logits_with_history(
+        &mut self,
+        context_indices: &[ContextIndex],
+        input_logits: &[T],
+        target: i32,
+        sample_weight: T,
+    ) -> LayerTrainHistory<T> {
+        let neuron_histories = self
+            .neurons
+            .iter_mut()
+            .zip(context_indices)
+            .map(|(neuron, context_index)| {
+                neuron.update_weights_by_logits_with_history(
+                    input_logits,
+                    target,
+                    *context_index,
+                    sample_weight,
+                )
+            })
+            .collect();
+        LayerTrainHistory { neuron_histories }
+    }
+
+    pub fn set_class_weights(&mut self, class_weights: ClassWeights<T>) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.set_class_weights(class_weights);
+        }
+    }
+
+    pub fn predict_logits_by_context_index(
+        &self,
+        context_indices: &[ContextIndex],
+        input_logits: &[T],
+    ) -> Vec<T> {
+        self.neurons
+            .iter()
+            .zip(context_indices)
+            .map(|(neuron, context_index)| {
+                neuron.predict_logit_by_context_index(*context_index, input_logits)
+            })
+            .collect()
+    }
+
+    // Writes the context index selected by each neuron into `context_indices`. Half-space
+    // contexts of all neurons are evaluated at once with `projections` as scratch space.
+    pub fn select_context_indices_into(
+        &self,
+        features: &DVector<T>,
+        projections: &mut DVector<T>,
+        context_indices: &mut Vec<ContextIndex>,
+    ) {
+        match self.context_evaluator.as_ref() {
+            Some(evaluator) => evaluator.context_indices_into(features, projections, context_indices),
+            None => {
+                let num_evaluated = Self::num_evaluated(self.num_neurons, self.context_sharing);
+                context_indices.clear();
+                context_indices.extend(
+                    self.neurons[..num_evaluated]
+                        .iter()
+                        .map(|neuron| neuron.select_context_index(features)),
+                );
+            }
+        }
+        if self.context_sharing == ContextSharing::SharedEvaluation {
+            if let Some(&context_index) = context_indices.first() {
+                context_indices.resize(self.num_neurons, context_index);
+            }
+        }
+    }
+
+    // Allocation-free version of `predict_logits_by_context_index`.
+    pub fn predict_logits_into(
+        &self,
+        context_indices: &[ContextIndex],
+        input_logits: &[T],
+        logits: &mut Vec<T>,
+    ) {
+        logits.clear();
+        logits.extend(self.neurons.iter().zip(context_indices).map(|(neuron, context_index)| {
+            neuron.predict_logit_by_context_index(*context_index, input_logits)
+        }));
+    }
+
+    // Unclipped logits used for prediction, equal to the selected weight matrix times `input_logits`.
+    pub fn mix_logits_into(
+        &self,
+        context_indices: &[ContextIndex],
+        input_logits: &[T],
+        logits: &mut Vec<T>,
+    ) {
+        logits.clear();
+        logits.extend(self.neurons.iter().zip(context_indices).map(|(neuron, context_index)| {
+            neuron.mix_logits_by_context_index(*context_index, input_logits)
+        }));
+    }
+
+    pub fn train_by_logits_with_scratch(
+        &mut self,
+        context_indices: &[ContextIndex],
+        input_logits: &[T],
+        target: i32,
+        sample_weight: T,
+        scratch: &mut Vec<T>,
+    ) {
+        for (neuron, context_index) in self.neurons.iter_mut().zip(context_indices) {
+            neuron.update_weights_by_logits_with_scratch(
+                input_logits,
+                target,
+                *context_index,
+                sample_weight,
+                scratch,
+            );
+        }
+    }
+
+    pub fn visit_counts(&self, context_indices: &[ContextIndex]) -> Vec<usize> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .map(|(neuron_id, neuron)| neuron.get_visit_count(context_indices[neuron_id]))
+            .collect()
+    }
+
+    pub fn num_neurons(&self) -> usize {
+        self.num_neurons
+    }
+
+    pub fn input_dim(&self) -> usize {
+        self.input_dim
+    }
+
+    // Seeds each adapted neuron from `rng`. Only the first neuron adapts when the
+    // contexts are shared, and the others follow it.
+    pub fn set_context_adaptation<R: Rng>(&mut self, config: ContextAdaptationConfig<T>, rng: &mut R) {
+        let num_adapted = match self.context_sharing {
+            ContextSharing::Independent => self.num_neurons,
+            _ => self.num_neurons.min(1),
+        };
+        for (neuron_id, neuron) in self.neurons.iter_mut().enumerate() {
+            let context_dim = neuron.context_func().context_dim();
+            let adaptation = if neuron_id < num_adapted {
+                config.build(context_dim, rng.gen())
+            } else {
+                None
+            };
+            neuron.set_context_adaptation(adaptation);
+        }
+    }
+
+    // Returns whether any context changed, in which case the evaluator is rebuilt.
+    pub fn adapt_contexts(&mut self, features: &DVector<T>) -> bool {
+        let changed_bits: Vec<ContextIndex> = self
+            .neurons
+            .iter_mut()
+            .map(|neuron| neuron.adapt_context(features))
+            .collect();
+        if changed_bits.iter().all(|bits| *bits == 0) {
+            return false;
+        }
+
+        // The neurons following the first one take over its changed context, so they reset too.
+        if self.context_sharing != ContextSharing::Independent && changed_bits[0] != 0 {
+            for neuron in self.neurons.iter_mut().skip(1) {
+                neuron.reset_weights();
+            }
+        }
+        self.set_context_sharing(self.context_sharing);
+        true
+    }
+
+    pub fn set_weight_projection(&mut self, projection: WeightProjection) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.set_weight_projection(projection);
+        }
+    }
+
+    pub fn set_forgetting(&mut self, forgetting: ForgettingConfig<T>) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.set_forgetting(forgetting);
+        }
+    }
+
+    pub fn shrink_to_initial_weights(&mut self, rate: T) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.shrink_to_initial_weights(rate);
+        }
+    }
+
+    pub fn initialize_weights(&mut self, initialization: WeightInitialization<T>) {
+        // Only uniform initialization draws from the rng.
+        let seed = match initialization {
+            WeightInitialization::Uniform { seed, .. } => seed,
+            _ => 0,
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for neuron in self.neurons.iter_mut() {
+            let context_dim = neuron.context_func().context_dim();
+            let weights = match initialization {
+                WeightInitialization::Balanced => initialize_balanced_weights(self.input_dim, context_dim),
+                WeightInitialization::Zero => initialize_zero_weights(self.input_dim, context_dim),
+                WeightInitialization::Uniform { low, high, .. } => {
+                    initialize_uniform_weights(self.input_dim, context_dim, low, high, &mut rng)
+                }
+                WeightInitialization::Identity { input_index } => {
+                    initialize_identity_weights(self.input_dim, context_dim, input_index)
+                }
+            };
+            neuron.reinitialize_weights(weights);
+        }
+    }
+
+    pub fn calculate_next_weight_matrix(
+        &self,
+        features: &DVector<T>,
+        previous_vector: &DMatrix<T>,
+    ) -> LayerPrediction<T> {
+        let (weight_matrix, context_index_map) = self.select_weight_matrix(features);
+
+        LayerPrediction {
+            predictions: (weight_matrix * previous_vector),
+            context_index_map: Some(context_index_map),
+        }
+    }
+
+    // Weights selected by each neuron's context, one row per neuron.
+    pub fn select_weight_matrix(
+        &self,
+        features: &DVector<T>,
+    ) -> (DMatrix<T>, Vec<ContextIndex>) {
+        let mut context_index_map = Vec::with_capacity(self.num_neurons);
+        self.select_context_indices_into(features, &mut DVector::zeros(0), &mut context_index_map);
+        let mut weight_vec = Vec::with_capacity(self.num_neurons * self.input_dim);
+        for (neuron, context_index) in self.neurons.iter().zip(&context_index_map) {
+            weight_vec.extend_from_slice(&neuron.get_weights(*context_index));
+        }
+
+        let weight_matrix = DMatrix::from_row_slice(self.num_neurons, self.input_dim, &weight_vec);
+        (weight_matrix, context_index_map)
+    }
+
+    pub fn context_bits(&self, features: &DVector<T>) -> HashMap<NeuronId, Vec<bool>> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .map(|(neuron_id, neuron)| (neuron_id, neuron.get_context_bits(features)))
+            .collect()
+    }
+}
+
+impl<T: GLNFloat> Layer<T> {
     pub fn with_neuron_num(
         neuron_num: usize,
         input_dim: usize,
         context_dim: usize,
         feature_dim: usize,
-        learning_rate: f32,
-        weight_clipping_value: f32,
-        negative_weight: f32,
-        reg_param: f32,
+        learning_rate: T,
+        weight_clipping_value: T,
+        negative_weight: T,
+        reg_param: T,
     ) -> Self {
-        let neurons: Vec<Neuron<HalfSpaceContext>> = (0usize..neuron_num)
+        let neurons: Vec<Neuron<HalfSpaceContext<T>, T>> = (0usize..neuron_num)
             .map(|_| {
                 Neuron::with_half_space_context(
                     input_dim,
@@ -59,88 +453,219 @@ impl Layer {
         Self::new(neurons, input_dim)
     }
 
-    pub fn train(
+    pub fn to_state(&self) -> LayerState {
+        LayerState {
+            input_dim: self.input_dim,
+            neurons: self.neurons.iter().map(|neuron| neuron.to_state()).collect(),
+            context_sharing: self.context_sharing,
+            masked_features: self.masked_features.clone(),
+        }
+    }
+
+    pub fn from_state(state: &LayerState) -> Self {
+        let neurons = state.neurons.iter().map(Neuron::from_state).collect();
+        let mut layer = Layer::new(neurons, state.input_dim);
+        layer.masked_features = state.masked_features.clone();
+        layer.context_sharing = state.context_sharing;
+        layer.mask_contexts();
+        layer
+    }
+
+    pub fn resample_contexts<R: Rng>(&mut self, rng: &mut R) {
+        if self.context_sharing == ContextSharing::Independent {
+            for neuron in self.neurons.iter_mut() {
+                neuron.resample_context(rng);
+            }
+        } else if let Some(first) = self.neurons.first_mut() {
+            first.resample_context(rng);
+        }
+        self.mask_contexts();
+    }
+
+    // Replaces the contexts with ones placed by `sampling` on `calibration_data`.
+    pub fn sample_contexts<R: Rng>(
         &mut self,
-        context_index_map: &HashMap<NeuronId, ContextIndex>,
-        inputs: &Vec<f32>,
-        target: i32,
+        sampling: HyperplaneSampling,
+        calibration_data: &[DVector<T>],
+        rng: &mut R,
     ) {
-        for neuron_id in 0usize..self.num_neurons {
-            self.neurons[neuron_id].update_weights(inputs, target, context_index_map[&neuron_id]);
+        let num_sampled = match self.context_sharing {
+            ContextSharing::Independent => self.num_neurons,
+            _ => self.num_neurons.min(1),
+        };
+        for neuron in self.neurons.iter_mut().take(num_sampled) {
+            let context = neuron.context_func();
+            neuron.set_context_func(HalfSpaceContext::from_data(
+                context.context_dim(),
+                context.feature_dim(),
+                calibration_data,
+                sampling,
+                rng,
+            ));
         }
+        self.mask_contexts();
     }
 
-    pub fn predict_by_context_index(
-        &self,
-        context_index_map: &HashMap<NeuronId, ContextIndex>,
-        inputs: &Vec<f32>,
-    ) -> Vec<f32> {
-        let mut probabilities = Vec::with_capacity(self.num_neurons);
-        for neuron_id in 0usize..self.num_neurons {
-            let probability = self.neurons[neuron_id]
-                .predict_by_context_index(context_index_map[&neuron_id], inputs);
-            probabilities.push(probability);
+    // Constrains the weight signs of the neurons by `input_monotonicity`, one entry per input,
+    // and makes the contexts ignore the base inputs in `masked_features`.
+    pub fn set_monotonicity(&mut self, input_monotonicity: Vec<Monotonicity>, masked_features: Vec<usize>) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.set_input_monotonicity(input_monotonicity.clone());
         }
-        probabilities
+        self.masked_features = masked_features;
+        self.mask_contexts();
     }
 
-    pub fn calculate_next_weight_matrix(
-        &self,
-        features: &DVector<f32>,
-        previous_vector: &DMatrix<f32>,
-    ) -> LayerPrediction {
-        let mut weight_vec = Vec::new();
-        let mut context_index_map = HashMap::new();
-        for (neuron_id, neuron) in self.neurons.iter().enumerate() {
-            let (weights, context_index) = neuron.get_current_weights(features);
-            weight_vec.append(&mut weights.clone());
-            context_index_map.insert(neuron_id, context_index);
+    // Starts every neuron from the neuron with the same id in `source`, which must have as many
+    // neurons and inputs but may use a different context dimension. The neuron takes over the
+    // source's hyperplanes as its first ones, so the contexts they share keep their weights.
+    pub fn warm_start_from(&mut self, source: &Layer<T>) {
+        assert_eq!(self.num_neurons, source.num_neurons, "the source layer has a different number of neurons");
+        for (neuron, source_neuron) in self.neurons.iter_mut().zip(&source.neurons) {
+            let mut context_func = neuron.context_func().clone();
+            context_func.copy_hyperplanes_from(source_neuron.context_func());
+            let weights = initialize_from_gate(source_neuron.gate(), self.input_dim, context_func.context_dim());
+            neuron.set_context_func(context_func);
+            neuron.reinitialize_weights(weights);
         }
+        self.mask_contexts();
+    }
 
-        let weight_matrix = DMatrix::from_row_slice(self.num_neurons, self.input_dim, &weight_vec);
-
-        LayerPrediction {
-            predictions: (weight_matrix * previous_vector),
-            context_index_map: Some(context_index_map),
+    // Makes the contexts of the neurons ignore `masked_features` and shares them again.
+    fn mask_contexts(&mut self) {
+        if !self.masked_features.is_empty() {
+            let num_masked = match self.context_sharing {
+                ContextSharing::Independent => self.num_neurons,
+                _ => self.num_neurons.min(1),
+            };
+            for neuron in self.neurons.iter_mut().take(num_masked) {
+                neuron.mask_context_features(&self.masked_features);
+            }
         }
+        self.set_context_sharing(self.context_sharing);
+    }
+}
+
+impl<T: GLNFloat, C: ContextFunction<T> + Clone + PartialEq> Layer<T, C> {
+    // Returns the id of the first neuron that cannot be merged with `other`'s, if any.
+    pub fn find_unmergeable_neuron(&self, other: &Layer<T, C>) -> Option<NeuronId> {
+        self.neurons
+            .iter()
+            .zip(&other.neurons)
+            .position(|(neuron, other_neuron)| !neuron.is_mergeable_with(other_neuron))
+    }
+
+    pub fn merge(layers: &[&Layer<T, C>]) -> Layer<T, C> {
+        let neurons = (0..layers[0].num_neurons)
+            .map(|neuron_id| {
+                let neurons: Vec<_> = layers.iter().map(|layer| &layer.neurons[neuron_id]).collect();
+                Neuron::merge(&neurons)
+            })
+            .collect();
+        let mut layer = Layer::new(neurons, layers[0].input_dim);
+        layer.masked_features = layers[0].masked_features.clone();
+        layer.set_context_sharing(layers[0].context_sharing);
+        layer
     }
 }
 
-pub struct BaseLayer {
-    pred_clipping_value: f32,
+#[derive(Clone)]
+pub struct BaseLayer<T: GLNFloat = f32> {
+    pred_clipping_value: T,
     feature_dim: usize,
+    // Inputs passed through as probabilities instead of being min-max normalized with the
+    // others, so that their logit depends on nothing else. Empty when there are none.
+    monotone_inputs: Vec<bool>,
 }
 
-impl BaseLayer {
-    pub fn new(pred_clipping_value: f32, feature_dim: usize) -> Self {
+impl<T: GLNFloat> BaseLayer<T> {
+    pub fn new(pred_clipping_value: T, feature_dim: usize) -> Self {
         BaseLayer {
             pred_clipping_value,
             feature_dim,
+            monotone_inputs: Vec::new(),
+        }
+    }
+
+    pub fn to_state(&self) -> BaseLayerState {
+        BaseLayerState {
+            pred_clipping_value: to_f64(self.pred_clipping_value),
+            feature_dim: self.feature_dim,
+            monotone_inputs: self.monotone_inputs.clone(),
         }
     }
 
-    pub fn predict(&self, features: &DVector<f32>) -> Vec<f32> {
+    pub fn from_state(state: &BaseLayerState) -> Self {
+        let mut base_layer = BaseLayer::new(from_f64(state.pred_clipping_value), state.feature_dim);
+        base_layer.monotone_inputs = state.monotone_inputs.clone();
+        base_layer
+    }
+
+    pub fn set_monotone_inputs(&mut self, monotone_inputs: Vec<bool>) {
+        self.monotone_inputs = monotone_inputs;
+    }
+
+    pub fn feature_dim(&self) -> usize {
+        self.feature_dim
+    }
+
+    // Minimum and maximum of the features that are min-max normalized.
+    fn normalized_range(&self, features: &DVector<T>) -> (T, T) {
+        if self.monotone_inputs.is_empty() {
+            return (features.min(), features.max());
+        }
+        features
+            .iter()
+            .zip(&self.monotone_inputs)
+            .filter(|(_, monotone)| !**monotone)
+            .fold(None, |range: Option<(T, T)>, (value, _)| match range {
+                Some((min_value, max_value)) => Some((min_value.min(*value), max_value.max(*value))),
+                None => Some((*value, *value)),
+            })
+            .unwrap_or((T::zero(), T::zero()))
+    }
+
+    fn pass_through_monotone_inputs(&self, features: &DVector<T>, logits: &mut [T]) {
+        for ((logit_value, value), monotone) in logits.iter_mut().zip(features.iter()).zip(&self.monotone_inputs) {
+            if *monotone {
+                *logit_value = logit(clip_prob(*value, self.pred_clipping_value));
+            }
+        }
+    }
+
+    pub fn predict(&self, features: &DVector<T>) -> Vec<T> {
         self.normalize(features)
     }
 
-    pub fn predict_through_logits(&self, features: &DVector<f32>) -> LayerPrediction {
-        let max_value = features.max();
-        let min_value = features.min();
+    pub fn predict_logits(&self, features: &DVector<T>) -> Vec<T> {
+        let mut logits = Vec::with_capacity(features.len());
+        self.predict_logits_into(features, &mut logits);
+        logits
+    }
 
-        let base_predictions = if max_value != min_value {
-            features
-                .iter()
-                .map(|value| (value - min_value) / (max_value - min_value))
-                .map(|value| logit(clip_prob(value, self.pred_clipping_value)))
-                .collect::<Vec<f32>>()
+    // Allocation-free version of `predict_logits`.
+    pub fn predict_logits_into(&self, features: &DVector<T>, logits: &mut Vec<T>) {
+        logits.clear();
+        let (min_value, max_value) = self.normalized_range(features);
+
+        if max_value != min_value {
+            logits.extend(
+                features
+                    .iter()
+                    .map(|value| (*value - min_value) / (max_value - min_value))
+                    .map(|value| logit(clip_prob(value, self.pred_clipping_value))),
+            );
         } else {
-            let feature_len = features.len();
-            features
-                .iter()
-                .map(|value| value / feature_len as f32)
-                .map(|value| logit(clip_prob(value, self.pred_clipping_value)))
-                .collect::<Vec<f32>>()
-        };
+            // Constant features all map to their common value.
+            let value = logit(clip_prob(max_value, self.pred_clipping_value));
+            logits.extend(features.iter().map(|_| value));
+        }
+        self.pass_through_monotone_inputs(features, logits);
+    }
+
+    pub fn predict_through_logits(&self, features: &DVector<T>) -> LayerPrediction<T> {
+        let mut base_predictions = Vec::with_capacity(features.len());
+        self.predict_through_logits_into(features, &mut base_predictions);
 
         LayerPrediction {
             predictions: DMatrix::from_row_slice(self.feature_dim, 1, &base_predictions),
@@ -148,37 +673,64 @@ impl BaseLayer {
         }
     }
 
-    fn normalize(&self, features: &DVector<f32>) -> Vec<f32> {
+    // Base logits used for prediction, without building a matrix.
+    pub fn predict_through_logits_into(&self, features: &DVector<T>, logits: &mut Vec<T>) {
+        logits.clear();
+        let (min_value, max_value) = self.normalized_range(features);
+
+        if max_value != min_value {
+            logits.extend(
+                features
+                    .iter()
+                    .map(|value| (*value - min_value) / (max_value - min_value))
+                    .map(|value| logit(clip_prob(value, self.pred_clipping_value))),
+            );
+        } else {
+            let feature_len: T = convert(features.len() as f64);
+            logits.extend(
+                features
+                    .iter()
+                    .map(|value| *value / feature_len)
+                    .map(|value| logit(clip_prob(value, self.pred_clipping_value))),
+            );
+        }
+        self.pass_through_monotone_inputs(features, logits);
+    }
+
+    fn normalize(&self, features: &DVector<T>) -> Vec<T> {
         let max_value = features.max();
         let min_value = features.min();
 
         if max_value != min_value {
             features
                 .iter()
-                .map(|value| (value - min_value) / (max_value - min_value))
+                .map(|value| (*value - min_value) / (max_value - min_value))
                 .map(|value| clip_prob(value, self.pred_clipping_value))
-                .collect::<Vec<f32>>()
-        } else if max_value != 0.0 {
+                .collect::<Vec<T>>()
+        } else if max_value != T::zero() {
             features
                 .iter()
-                .map(|_| max_value as f32)
+                .map(|_| max_value)
                 .map(|value| clip_prob(value, self.pred_clipping_value))
-                .collect::<Vec<f32>>()
+                .collect::<Vec<T>>()
         } else {
             features
                 .iter()
-                .map(|_| 0.0 as f32)
+                .map(|_| T::zero())
                 .map(|value| clip_prob(value, self.pred_clipping_value))
-                .collect::<Vec<f32>>()
+                .collect::<Vec<T>>()
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
     use nalgebra::{DMatrix, DVector};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
 
+    use crate::model::adaptation::ContextAdaptationConfig;
+    use crate::model::config::ContextSharing;
     use crate::model::layer::{BaseLayer, Layer};
 
     #[test]
@@ -192,7 +744,7 @@ mod tests {
         let negative_weight = 1.0;
         let reg_param = 1.0;
 
-        let mut layer = Layer::with_neuron_num(
+        let mut layer: Layer = Layer::with_neuron_num(
             neuron_num,
             input_dim,
             context_dim,
@@ -202,21 +754,85 @@ mod tests {
             negative_weight,
             reg_param);
 
-        let context_index_map = HashMap::from([
-            (0, 1),
-            (1, 3),
-            (2, 5)
-        ]);
+        let context_indices = vec![1, 3, 5];
         let inputs = vec![0.3, 0.2, 0.5];
         let target = 1;
 
-        layer.train(&context_index_map, &inputs, target);
+        layer.train(&context_indices, &inputs, target);
+    }
+
+    #[test]
+    fn test_mix_logits_into_matches_weight_matrix() {
+        let layer: Layer<f64> = Layer::with_neuron_num(3, 4, 2, 4, 0.1, 5.0, 1.0, 0.0);
+        let features = DVector::from_vec(vec![0.3, -0.1, 0.8, 0.2]);
+        let input_logits = vec![0.5, -1.2, 2.0, 0.1];
+
+        let mut context_indices = Vec::new();
+        layer.select_context_indices_into(&features, &mut DVector::zeros(0), &mut context_indices);
+        let mut logits = Vec::new();
+        layer.mix_logits_into(&context_indices, &input_logits, &mut logits);
+
+        let (weight_matrix, expected_indices) = layer.select_weight_matrix(&features);
+        let expected = weight_matrix * DVector::from_vec(input_logits);
+        assert_eq!(context_indices, expected_indices);
+        for (neuron, context_index) in layer.neurons.iter().zip(&context_indices) {
+            assert_eq!(neuron.select_context_index(&features), *context_index);
+        }
+        for (actual, expected) in logits.iter().zip(expected.iter()) {
+            assert!((actual - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_shared_context_adaptation_resets_every_neuron() {
+        let mut layer = Layer::<f64>::with_neuron_num(3, 2, 2, 3, 0.1, 5.0, 1.0, 0.0);
+        layer.set_context_sharing(ContextSharing::Shared);
+        layer.set_context_adaptation(
+            ContextAdaptationConfig::RedrawImbalanced {
+                max_imbalance: 0.9,
+                min_samples: 10,
+            },
+            &mut ChaCha8Rng::seed_from_u64(0),
+        );
+        for context_index in 0..4 {
+            layer.train(&[context_index; 3], &vec![0.9, 0.2], 1);
+        }
+
+        let features = DVector::from_vec(vec![50.0, 50.0, 50.0]);
+        let num_changes = (0..10).filter(|_| layer.adapt_contexts(&features)).count();
+        assert_eq!(num_changes, 1);
+        for neuron in layer.neurons.iter() {
+            for context_index in 0..4 {
+                assert_eq!(neuron.get_weights(context_index), vec![0.5, 0.5]);
+                assert_eq!(neuron.get_visit_count(context_index), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_contexts_select_the_same_index() {
+        for context_sharing in vec![ContextSharing::Shared, ContextSharing::SharedEvaluation] {
+            let mut layer: Layer<f64> = Layer::with_neuron_num(5, 3, 3, 4, 0.1, 5.0, 1.0, 0.0);
+            layer.set_context_sharing(context_sharing);
+            for neuron in layer.neurons.iter() {
+                assert!(neuron.context_func().shares_hyperplanes_with(layer.neurons[0].context_func()));
+            }
+
+            let mut context_indices = Vec::new();
+            for seed in 0..20 {
+                let features = DVector::from_fn(4, |row, _| ((seed * 7 + row * 3) % 11) as f64 / 5.0 - 1.0);
+                layer.select_context_indices_into(&features, &mut DVector::zeros(0), &mut context_indices);
+                assert_eq!(context_indices.len(), 5);
+                assert!(context_indices.iter().all(|index| *index == context_indices[0]));
+                assert_eq!(layer.neurons[4].select_context_index(&features), context_indices[0]);
+            }
+        }
     }
 
     #[test]
     fn test_base_layer_predict() {
         let features = vec![1.0, 5.0, 4.0, 4.0];
-        let base_layer = BaseLayer::new(0.01, features.len());
+        let base_layer: BaseLayer = BaseLayer::new(0.01, features.len());
         let feature_vec = DVector::from_vec(features);
         let actual = base_layer.predict(&feature_vec);
 
@@ -227,7 +843,7 @@ mod tests {
     #[test]
     fn test_predict_through_logit() {
         let features = vec![8.0, 4.0, 6.0, 3.0];
-        let base_layer = BaseLayer::new(0.01, features.len());
+        let base_layer: BaseLayer = BaseLayer::new(0.01, features.len());
         let feature_vec = DVector::from_vec(features);
 
         let actual = base_layer.predict_through_logits(&feature_vec);
@@ -240,7 +856,7 @@ mod tests {
     #[test]
     fn test_predict_through_logit_all_zero() {
         let features = vec![0.0, 0.0, 0.0, 0.0];
-        let base_layer = BaseLayer::new(0.01, features.len());
+        let base_layer: BaseLayer = BaseLayer::new(0.01, features.len());
         let feature_vec = DVector::from_vec(features);
 
         let actual = base_layer.predict_through_logits(&feature_vec);
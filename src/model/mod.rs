@@ -1,6 +1,12 @@
+pub mod adaptation;
+pub mod calibration;
 pub mod config;
 pub mod context_func;
+pub mod drift;
 pub mod gate;
 pub mod gln_model;
 pub mod layer;
 pub mod neuron;
+pub mod observer;
+pub mod persistence;
+pub mod shared_model;
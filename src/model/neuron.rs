@@ -1,60 +1,150 @@
-use nalgebra::DVector;
+use nalgebra::{convert, DVector};
+use rand::Rng;
 
-use crate::model::config::LayerConfig;
-use crate::model::context_func::{ContextFunction, HalfSpaceContext, SkipGramContext};
+use crate::model::adaptation::ContextAdaptation;
+use crate::model::config::{
+    ClassWeights, ForgettingConfig, LayerConfig, Monotonicity, WeightProjection,
+};
+use crate::model::context_func::{ContextFunction, HalfSpaceContext};
 use crate::model::gate::{Gate, initialize_balanced_weights};
+use crate::model::persistence::{from_f64, to_f64, NeuronState};
 use crate::optimize::grad::{LogGeometricMixingGradient, OnlineGradient};
 use crate::optimize::optimizer::OnlineGradientDecent;
 use crate::utils::data_type::ContextIndex;
-use crate::utils::math::{clip_hypercube, clip_prob, geometric_mixing};
-
-pub struct Neuron<C: ContextFunction> {
-    gate: Gate<C>,
-    optimizer: OnlineGradientDecent,
-    gradient: LogGeometricMixingGradient,
-    pred_clipping_value: f32,
-    weight_clipping_value: f32,
+use crate::utils::float::GLNFloat;
+use crate::utils::math::{
+    clip_logit, clip_prob, geometric_mixing, geometric_mixing_logit,
+    geometric_mixing_logit_loss, logit, sigmoid,
+};
+
+#[derive(Clone)]
+pub struct Neuron<C: ContextFunction<T>, T: GLNFloat = f32> {
+    gate: Gate<C, T>,
+    optimizer: OnlineGradientDecent<T>,
+    gradient: LogGeometricMixingGradient<T>,
+    pred_clipping_value: T,
+    weight_clipping_value: T,
+    projection: WeightProjection,
+    // Sign constraint of each input's weights, empty when no input is constrained.
+    input_monotonicity: Vec<Monotonicity>,
 }
 
-pub struct NeuronTrainHistory {
-    prediction: f32,
-    loss: f32,
+pub struct NeuronTrainHistory<T: GLNFloat = f32> {
+    pub context_index: ContextIndex,
+    // Probability and weighted loss before the update.
+    pub prediction: T,
+    pub loss: T,
+    pub weight_delta: Vec<T>,
+    // Norm of the loss gradient with respect to the weights.
+    pub gradient_norm: T,
+    // Updated weights on the boundary of the weight projection, e.g. at the weight clipping value.
+    pub num_saturated_weights: usize,
+    // Whether the mixed logit was clipped to the prediction clipping value.
+    pub prediction_clipped: bool,
+    // Input logits at the prediction clipping value.
+    pub num_clipped_inputs: usize,
 }
 
-impl Neuron<HalfSpaceContext> {
+impl<T: GLNFloat> Neuron<HalfSpaceContext<T>, T> {
     pub fn with_half_space_context(
         input_dim: usize,
         context_dim: usize,
         feature_dim: usize,
-        learning_rate: f32,
-        weight_clipping_value: f32,
-        negative_weight: f32,
-        reg_param: f32,
-    ) -> Neuron<HalfSpaceContext> {
-        let config = LayerConfig::with_default_value();
+        learning_rate: T,
+        weight_clipping_value: T,
+        negative_weight: T,
+        reg_param: T,
+    ) -> Neuron<HalfSpaceContext<T>, T> {
+        Neuron::with_context_func(
+            input_dim,
+            HalfSpaceContext::new(context_dim, feature_dim),
+            learning_rate,
+            weight_clipping_value,
+            negative_weight,
+            reg_param,
+        )
+    }
+}
+
+impl<T: GLNFloat> Neuron<HalfSpaceContext<T>, T> {
+    pub fn resample_context<R: Rng>(&mut self, rng: &mut R) {
+        self.gate.resample_context(rng);
+    }
+
+    pub fn mask_context_features(&mut self, feature_indices: &[usize]) {
+        let mut context_func = self.context_func().clone();
+        context_func.mask_features(feature_indices);
+        self.set_context_func(context_func);
+    }
+
+    pub fn to_state(&self) -> NeuronState {
+        let class_weights = self.gradient.class_weights();
+        NeuronState {
+            gate: self.gate.to_state(),
+            learning_rate: to_f64(self.optimizer.learning_rate()),
+            reg_param: to_f64(self.gradient.reg_param()),
+            positive_weight: to_f64(class_weights.positive),
+            negative_weight: to_f64(class_weights.negative),
+            pred_clipping_value: to_f64(self.pred_clipping_value),
+            weight_clipping_value: to_f64(self.weight_clipping_value),
+            projection: self.projection,
+            input_monotonicity: self.input_monotonicity.clone(),
+        }
+    }
+
+    pub fn from_state(state: &NeuronState) -> Self {
         Neuron {
-            gate: Gate::<HalfSpaceContext>::new(
-                input_dim,
-                context_dim,
-                feature_dim,
-                initialize_balanced_weights,
+            gate: Gate::from_state(&state.gate),
+            optimizer: OnlineGradientDecent::new(from_f64(state.learning_rate)),
+            gradient: LogGeometricMixingGradient::with_class_weights(
+                from_f64(state.reg_param),
+                ClassWeights::new(from_f64(state.positive_weight), from_f64(state.negative_weight)),
             ),
-            optimizer: OnlineGradientDecent::new(learning_rate),
-            gradient: LogGeometricMixingGradient::new(reg_param, negative_weight),
-            pred_clipping_value: config.pred_clipping_value,
-            weight_clipping_value: weight_clipping_value,
+            pred_clipping_value: from_f64(state.pred_clipping_value),
+            weight_clipping_value: from_f64(state.weight_clipping_value),
+            projection: state.projection,
+            input_monotonicity: state.input_monotonicity.clone(),
         }
     }
 }
 
-impl Neuron<SkipGramContext> {
-    pub fn with_skip_gram_context(_input_dim: usize, _context_dim: usize, _feature_dim: usize) {
-        todo!()
+impl<C: ContextFunction<T> + Clone + PartialEq, T: GLNFloat> Neuron<C, T> {
+    pub fn is_mergeable_with(&self, other: &Neuron<C, T>) -> bool {
+        self.gate.is_mergeable_with(&other.gate)
+    }
+
+    pub fn merge(neurons: &[&Neuron<C, T>]) -> Neuron<C, T> {
+        let gates: Vec<_> = neurons.iter().map(|neuron| &neuron.gate).collect();
+        let mut merged = neurons[0].clone();
+        merged.gate = Gate::merge(&gates);
+        merged
     }
 }
 
-impl<C: ContextFunction> Neuron<C> {
-    pub fn predict_by_context_index(&self, context_index: ContextIndex, inputs: &Vec<f32>) -> f32 {
+impl<C: ContextFunction<T>, T: GLNFloat> Neuron<C, T> {
+    // Balanced initial weights for every context of `context_func`. With a `FixedContext`
+    // the neuron is a plain geometric mixer.
+    pub fn with_context_func(
+        input_dim: usize,
+        context_func: C,
+        learning_rate: T,
+        weight_clipping_value: T,
+        negative_weight: T,
+        reg_param: T,
+    ) -> Neuron<C, T> {
+        let config = LayerConfig::with_default_value();
+        Neuron {
+            gate: Gate::from_context_func(input_dim, context_func, initialize_balanced_weights),
+            optimizer: OnlineGradientDecent::new(learning_rate),
+            gradient: LogGeometricMixingGradient::new(reg_param, negative_weight),
+            pred_clipping_value: config.pred_clipping_value,
+            weight_clipping_value: weight_clipping_value,
+            projection: WeightProjection::default(),
+            input_monotonicity: Vec::new(),
+        }
+    }
+
+    pub fn predict_by_context_index(&self, context_index: ContextIndex, inputs: &Vec<T>) -> T {
         let current_weights = self.gate.get_weights(context_index);
 
         let prediction = clip_prob(
@@ -65,7 +155,7 @@ impl<C: ContextFunction> Neuron<C> {
         prediction
     }
 
-    pub fn update_weights(&mut self, inputs: &Vec<f32>, target: i32, context_index: ContextIndex) {
+    pub fn update_weights(&mut self, inputs: &Vec<T>, target: i32, context_index: ContextIndex) {
         let mut updated_weights = Vec::with_capacity(inputs.len());
         let current_weights = self.gate.get_weights(context_index);
 
@@ -78,29 +168,219 @@ impl<C: ContextFunction> Neuron<C> {
                 self.pred_clipping_value,
             );
 
-            let updated_weight = self.optimizer.update(current_weights[weight_index], grad);
-            updated_weights.push(clip_hypercube(updated_weight, self.weight_clipping_value));
+            updated_weights.push(self.optimizer.update(current_weights[weight_index], grad));
         }
+        self.project_weights(&mut updated_weights);
 
         self.gate.update_weights(context_index, updated_weights);
     }
 
-    pub fn get_current_weights(&self, features: &DVector<f32>) -> (Vec<f32>, usize) {
+    pub fn predict_logit_by_context_index(
+        &self,
+        context_index: ContextIndex,
+        input_logits: &[T],
+    ) -> T {
+        clip_logit(
+            self.mix_logits_by_context_index(context_index, input_logits),
+            self.pred_clipping_value,
+        )
+    }
+
+    // Geometric mixing of `input_logits` without clipping, as used for prediction.
+    pub fn mix_logits_by_context_index(&self, context_index: ContextIndex, input_logits: &[T]) -> T {
+        geometric_mixing_logit(input_logits, &self.gate.weights(context_index))
+    }
+
+    pub fn update_weights_by_logits(
+        &mut self,
+        input_logits: &[T],
+        target: i32,
+        context_index: ContextIndex,
+        sample_weight: T,
+    ) {
+        let mut updated_weights = Vec::with_capacity(input_logits.len());
+        self.update_weights_by_logits_with_scratch(
+            input_logits,
+            target,
+            context_index,
+            sample_weight,
+            &mut updated_weights,
+        );
+    }
+
+    // Same as `update_weights_by_logits`, computing the new weights in `scratch` so that
+    // repeated updates do not allocate.
+    pub fn update_weights_by_logits_with_scratch(
+        &mut self,
+        input_logits: &[T],
+        target: i32,
+        context_index: ContextIndex,
+        sample_weight: T,
+        scratch: &mut Vec<T>,
+    ) {
+        self.step_weights(input_logits, target, context_index, sample_weight, scratch);
+    }
+
+    // Takes one projected gradient step, and returns the squared norm of the gradient.
+    fn step_weights(
+        &mut self,
+        input_logits: &[T],
+        target: i32,
+        context_index: ContextIndex,
+        sample_weight: T,
+        scratch: &mut Vec<T>,
+    ) -> T {
+        scratch.clear();
+        let current_weights = self.gate.weights(context_index);
+        let mut squared_gradient_norm = T::zero();
+
+        for (weight_index, _) in current_weights.iter().enumerate() {
+            let grad = self.gradient.calculate_grad_by_logits(
+                input_logits,
+                target,
+                &current_weights,
+                weight_index,
+                sample_weight,
+            );
+            squared_gradient_norm += grad * grad;
+
+            scratch.push(self.optimizer.update(current_weights[weight_index], grad));
+        }
+        drop(current_weights);
+        self.project_weights(scratch);
+
+        self.gate.update_weights_from_slice(context_index, scratch);
+        squared_gradient_norm
+    }
+
+    // Same as `update_weights_by_logits`, also recording what the update did.
+    pub fn update_weights_by_logits_with_history(
+        &mut self,
+        input_logits: &[T],
+        target: i32,
+        context_index: ContextIndex,
+        sample_weight: T,
+    ) -> NeuronTrainHistory<T> {
+        let previous_weights = self.gate.get_weights(context_index);
+        let mixed_logit = self.mix_logits_by_context_index(context_index, input_logits);
+        let clipped_logit = clip_logit(mixed_logit, self.pred_clipping_value);
+        let mut scratch = Vec::with_capacity(input_logits.len());
+        let squared_gradient_norm =
+            self.step_weights(input_logits, target, context_index, sample_weight, &mut scratch);
+        let updated_weights = self.gate.get_weights(context_index);
+
+        // Clipped logits sit exactly at the edge up to rounding of the negative side.
+        let clipping_threshold = logit(T::one() - self.pred_clipping_value) * (T::one() - convert(1e-6));
+        NeuronTrainHistory {
+            context_index,
+            prediction: sigmoid(clipped_logit),
+            loss: sample_weight * geometric_mixing_logit_loss(target, clipped_logit),
+            weight_delta: updated_weights
+                .iter()
+                .zip(&previous_weights)
+                .map(|(updated, previous)| *updated - *previous)
+                .collect(),
+            gradient_norm: squared_gradient_norm.sqrt(),
+            num_saturated_weights: self.projection.num_saturated(&updated_weights, self.weight_clipping_value),
+            prediction_clipped: clipped_logit != mixed_logit,
+            num_clipped_inputs: input_logits
+                .iter()
+                .filter(|input_logit| input_logit.abs() >= clipping_threshold)
+                .count(),
+        }
+    }
+
+    pub fn set_class_weights(&mut self, class_weights: ClassWeights<T>) {
+        self.gradient.set_class_weights(class_weights);
+    }
+
+    pub fn set_forgetting(&mut self, forgetting: ForgettingConfig<T>) {
+        self.gate.set_forgetting(forgetting);
+    }
+
+    pub fn set_weight_projection(&mut self, projection: WeightProjection) {
+        self.projection = projection;
+    }
+
+    // Constrains the signs of the current and initial weights, and of every later update.
+    pub fn set_input_monotonicity(&mut self, input_monotonicity: Vec<Monotonicity>) {
+        self.input_monotonicity = input_monotonicity;
+        self.constrain_weights();
+    }
+
+    fn constrain_weights(&mut self) {
+        if !self.input_monotonicity.is_empty() {
+            let input_monotonicity = &self.input_monotonicity;
+            self.gate
+                .constrain_weights(|input_index, weight| input_monotonicity[input_index].constrain(weight));
+        }
+    }
+
+    // Sign constraints are applied after the projection, so they always hold.
+    fn project_weights(&self, weights: &mut [T]) {
+        self.projection.project(weights, self.weight_clipping_value);
+        for (weight, monotonicity) in weights.iter_mut().zip(&self.input_monotonicity) {
+            *weight = monotonicity.constrain(*weight);
+        }
+    }
+
+    pub fn shrink_to_initial_weights(&mut self, rate: T) {
+        self.gate.shrink_to_initial_weights(rate);
+    }
+
+    pub fn reset_weights(&mut self) {
+        self.gate.reset_weights();
+    }
+
+    pub fn reinitialize_weights(&mut self, weights: Vec<Vec<T>>) {
+        self.gate.reinitialize_weights(weights);
+        self.constrain_weights();
+    }
+
+    pub fn set_context_adaptation(&mut self, adaptation: Option<ContextAdaptation<T>>) {
+        self.gate.set_context_adaptation(adaptation);
+    }
+
+    pub fn adapt_context(&mut self, features: &DVector<T>) -> ContextIndex {
+        self.gate.adapt_context(features)
+    }
+
+    pub fn gate(&self) -> &Gate<C, T> {
+        &self.gate
+    }
+
+    pub fn get_visit_count(&self, context_index: ContextIndex) -> usize {
+        self.gate.get_visit_count(context_index)
+    }
+
+    pub fn get_weights(&self, context_index: ContextIndex) -> Vec<T> {
+        self.gate.get_weights(context_index)
+    }
+
+    pub fn get_current_weights(&self, features: &DVector<T>) -> (Vec<T>, usize) {
         let (current_weights, context_index) = self.gate.select_weights(features);
         (current_weights, context_index)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use mockall::mock;
+    pub fn context_func(&self) -> &C {
+        self.gate.context_func()
+    }
 
-    use crate::model::context_func::HalfSpaceContext;
-    use crate::model::gate::MockGate;
-    use crate::model::gate::initialize_balanced_weights;
-    use crate::model::neuron::Neuron;
+    pub fn set_context_func(&mut self, context_func: C) {
+        self.gate.set_context_func(context_func);
+    }
 
+    pub fn select_context_index(&self, features: &DVector<T>) -> ContextIndex {
+        self.gate.select_context_index(features)
+    }
 
+    pub fn get_context_bits(&self, features: &DVector<T>) -> Vec<bool> {
+        self.gate.context_bits(features)
+    }
+}
+
+#[cfg(test)]
+mod test {
     // mock! {
     //     #[automock]
     //     impl Gate {
@@ -0,0 +1,354 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use nalgebra::convert;
+
+use crate::model::layer::LayerTrainHistory;
+use crate::model::neuron::NeuronTrainHistory;
+use crate::model::persistence::to_f64;
+use crate::utils::data_type::{LayerId, NeuronId};
+use crate::utils::float::GLNFloat;
+
+pub struct ExampleTrainEvent<T: GLNFloat = f32> {
+    pub target: i32,
+    pub sample_weight: T,
+    // Uncalibrated output probability before the update.
+    pub prediction: T,
+    // Weighted loss of the output neuron before the update.
+    pub loss: T,
+    pub drift_detected: bool,
+}
+
+// Callbacks invoked while a GLN trains. Per-neuron and per-layer histories are only
+// collected when at least one observer is registered.
+pub trait TrainObserver<T: GLNFloat = f32> {
+    fn on_neuron_trained(
+        &mut self,
+        _layer_id: LayerId,
+        _neuron_id: NeuronId,
+        _history: &NeuronTrainHistory<T>,
+    ) {
+    }
+
+    fn on_layer_trained(&mut self, _layer_id: LayerId, _history: &LayerTrainHistory<T>) {}
+
+    fn on_example_trained(&mut self, _event: &ExampleTrainEvent<T>) {}
+}
+
+// Prints the mean output loss and accuracy of every `interval` examples.
+pub struct LoggingObserver<T: GLNFloat = f32> {
+    interval: usize,
+    num_examples: usize,
+    loss_sum: T,
+    num_correct: usize,
+}
+
+impl<T: GLNFloat> LoggingObserver<T> {
+    pub fn new(interval: usize) -> Self {
+        assert!(interval > 0, "the logging interval must be positive");
+        LoggingObserver {
+            interval,
+            num_examples: 0,
+            loss_sum: T::zero(),
+            num_correct: 0,
+        }
+    }
+}
+
+impl<T: GLNFloat> TrainObserver<T> for LoggingObserver<T> {
+    fn on_example_trained(&mut self, event: &ExampleTrainEvent<T>) {
+        self.num_examples += 1;
+        self.loss_sum += event.loss;
+        if (event.prediction > convert(0.5)) == (event.target == 1) {
+            self.num_correct += 1;
+        }
+
+        if self.num_examples.is_multiple_of(self.interval) {
+            println!(
+                "examples: {}, mean loss: {:.6}, accuracy: {:.4}",
+                self.num_examples,
+                to_f64(self.loss_sum) / self.interval as f64,
+                self.num_correct as f64 / self.interval as f64
+            );
+            self.loss_sum = T::zero();
+            self.num_correct = 0;
+        }
+    }
+}
+
+// Writes one CSV row per trained neuron:
+// example_id,layer_id,neuron_id,context_index,prediction,loss,weight_delta_norm
+pub struct CsvObserver<W: Write> {
+    writer: csv::Writer<W>,
+    example_id: usize,
+}
+
+impl<W: Write> CsvObserver<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "example_id",
+            "layer_id",
+            "neuron_id",
+            "context_index",
+            "prediction",
+            "loss",
+            "weight_delta_norm",
+        ])?;
+        Ok(CsvObserver {
+            writer,
+            example_id: 0,
+        })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<T: GLNFloat, W: Write> TrainObserver<T> for CsvObserver<W> {
+    fn on_neuron_trained(
+        &mut self,
+        layer_id: LayerId,
+        neuron_id: NeuronId,
+        history: &NeuronTrainHistory<T>,
+    ) {
+        let delta_norm = history
+            .weight_delta
+            .iter()
+            .map(|delta| to_f64(*delta).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let record = [
+            self.example_id.to_string(),
+            layer_id.to_string(),
+            neuron_id.to_string(),
+            history.context_index.to_string(),
+            to_f64(history.prediction).to_string(),
+            to_f64(history.loss).to_string(),
+            delta_norm.to_string(),
+        ];
+        if let Err(error) = self.writer.write_record(&record) {
+            eprintln!("failed to write a training record: {}", error);
+        }
+    }
+
+    fn on_example_trained(&mut self, _event: &ExampleTrainEvent<T>) {
+        self.example_id += 1;
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TrainMetrics {
+    pub num_examples: usize,
+    pub num_drifts: usize,
+    // Running means over all examples, by layer for `layer_mean_losses`.
+    pub mean_loss: f64,
+    pub accuracy: f64,
+    pub layer_mean_losses: Vec<f64>,
+}
+
+// Aggregates running training metrics that can be read from another handle while
+// the model owns the observer.
+pub struct MetricsObserver {
+    metrics: Arc<Mutex<TrainMetrics>>,
+}
+
+impl MetricsObserver {
+    pub fn new() -> Self {
+        MetricsObserver {
+            metrics: Arc::new(Mutex::new(TrainMetrics::default())),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<Mutex<TrainMetrics>> {
+        Arc::clone(&self.metrics)
+    }
+}
+
+impl Default for MetricsObserver {
+    fn default() -> Self {
+        MetricsObserver::new()
+    }
+}
+
+impl<T: GLNFloat> TrainObserver<T> for MetricsObserver {
+    fn on_layer_trained(&mut self, layer_id: LayerId, history: &LayerTrainHistory<T>) {
+        let mut metrics = self.metrics.lock().unwrap();
+        if metrics.layer_mean_losses.len() <= layer_id {
+            metrics.layer_mean_losses.resize(layer_id + 1, 0.0);
+        }
+        let losses = history.neuron_losses();
+        let layer_loss = losses.iter().map(|loss| to_f64(*loss)).sum::<f64>() / losses.len() as f64;
+        // Layers are trained before the example is counted.
+        let count = (metrics.num_examples + 1) as f64;
+        metrics.layer_mean_losses[layer_id] += (layer_loss - metrics.layer_mean_losses[layer_id]) / count;
+    }
+
+    fn on_example_trained(&mut self, event: &ExampleTrainEvent<T>) {
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.num_examples += 1;
+        let count = metrics.num_examples as f64;
+        let correct = (event.prediction > convert(0.5)) == (event.target == 1);
+        metrics.mean_loss += (to_f64(event.loss) - metrics.mean_loss) / count;
+        metrics.accuracy += (correct as i32 as f64 - metrics.accuracy) / count;
+        if event.drift_detected {
+            metrics.num_drifts += 1;
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TrainDiagnostics {
+    pub num_examples: usize,
+    // Running means over all examples, except `max_gradient_norm` which is the largest seen.
+    pub mean_gradient_norm: f64,
+    pub max_gradient_norm: f64,
+    pub saturated_weight_fraction: f64,
+    pub clipped_prediction_fraction: f64,
+    pub clipped_input_fraction: f64,
+}
+
+// Aggregates the `LayerDiagnostics` of every training step by layer. A saturated weight
+// fraction close to 1 means the weight projection, e.g. a too small weight clipping value,
+// keeps the model from learning.
+pub struct DiagnosticsObserver {
+    diagnostics: Arc<Mutex<Vec<TrainDiagnostics>>>,
+}
+
+impl DiagnosticsObserver {
+    pub fn new() -> Self {
+        DiagnosticsObserver {
+            diagnostics: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn diagnostics(&self) -> Arc<Mutex<Vec<TrainDiagnostics>>> {
+        Arc::clone(&self.diagnostics)
+    }
+}
+
+impl Default for DiagnosticsObserver {
+    fn default() -> Self {
+        DiagnosticsObserver::new()
+    }
+}
+
+impl<T: GLNFloat> TrainObserver<T> for DiagnosticsObserver {
+    fn on_layer_trained(&mut self, layer_id: LayerId, history: &LayerTrainHistory<T>) {
+        let mut diagnostics = self.diagnostics.lock().unwrap();
+        if diagnostics.len() <= layer_id {
+            diagnostics.resize(layer_id + 1, TrainDiagnostics::default());
+        }
+        let layer = history.diagnostics();
+        let running = &mut diagnostics[layer_id];
+        running.num_examples += 1;
+        let count = running.num_examples as f64;
+        running.mean_gradient_norm += (to_f64(layer.mean_gradient_norm) - running.mean_gradient_norm) / count;
+        running.max_gradient_norm = running.max_gradient_norm.max(to_f64(layer.max_gradient_norm));
+        running.saturated_weight_fraction +=
+            (to_f64(layer.saturated_weight_fraction) - running.saturated_weight_fraction) / count;
+        running.clipped_prediction_fraction +=
+            (to_f64(layer.clipped_prediction_fraction) - running.clipped_prediction_fraction) / count;
+        running.clipped_input_fraction +=
+            (to_f64(layer.clipped_input_fraction) - running.clipped_input_fraction) / count;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::model::layer::LayerTrainHistory;
+    use crate::model::neuron::NeuronTrainHistory;
+    use crate::model::observer::{
+        CsvObserver, DiagnosticsObserver, ExampleTrainEvent, LoggingObserver, MetricsObserver, TrainObserver,
+    };
+
+    fn neuron_history(loss: f32) -> NeuronTrainHistory {
+        NeuronTrainHistory {
+            context_index: 1,
+            prediction: 0.7,
+            loss,
+            weight_delta: vec![0.3, 0.4],
+            gradient_norm: loss * 10.0,
+            num_saturated_weights: 1,
+            prediction_clipped: loss > 0.5,
+            num_clipped_inputs: 0,
+        }
+    }
+
+    fn example_event(target: i32, loss: f32) -> ExampleTrainEvent {
+        ExampleTrainEvent {
+            target,
+            sample_weight: 1.0,
+            prediction: 0.7,
+            loss,
+            drift_detected: false,
+        }
+    }
+
+    #[test]
+    fn test_metrics_observer_aggregates_losses() {
+        let mut observer = MetricsObserver::new();
+        let metrics = observer.metrics();
+        for (target, loss) in vec![(1, 0.2), (0, 0.6)] {
+            let history = LayerTrainHistory {
+                neuron_histories: vec![neuron_history(loss), neuron_history(loss * 2.0)],
+            };
+            observer.on_layer_trained(0, &history);
+            observer.on_example_trained(&example_event(target, loss));
+        }
+
+        let metrics = metrics.lock().unwrap();
+        assert_eq!(metrics.num_examples, 2);
+        assert!((metrics.mean_loss - 0.4).abs() < 1e-6);
+        assert!((metrics.accuracy - 0.5).abs() < 1e-12);
+        assert!((metrics.layer_mean_losses[0] - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_diagnostics_observer_aggregates_layer_diagnostics() {
+        let mut observer = DiagnosticsObserver::new();
+        let diagnostics = observer.diagnostics();
+        for loss in vec![0.2, 0.4] {
+            let history = LayerTrainHistory {
+                neuron_histories: vec![neuron_history(loss), neuron_history(loss * 2.0)],
+            };
+            TrainObserver::<f32>::on_layer_trained(&mut observer, 1, &history);
+        }
+
+        let diagnostics = diagnostics.lock().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].num_examples, 0);
+        let layer = &diagnostics[1];
+        assert_eq!(layer.num_examples, 2);
+        assert!((layer.mean_gradient_norm - 4.5).abs() < 1e-5);
+        assert!((layer.max_gradient_norm - 8.0).abs() < 1e-5);
+        assert!((layer.saturated_weight_fraction - 0.5).abs() < 1e-12);
+        assert!((layer.clipped_prediction_fraction - 0.25).abs() < 1e-12);
+        assert_eq!(layer.clipped_input_fraction, 0.0);
+    }
+
+    #[test]
+    fn test_csv_observer_writes_a_row_per_neuron() {
+        let mut observer = CsvObserver::new(Vec::new()).unwrap();
+        TrainObserver::<f32>::on_neuron_trained(&mut observer, 0, 0, &neuron_history(0.2));
+        TrainObserver::<f32>::on_neuron_trained(&mut observer, 0, 1, &neuron_history(0.3));
+        observer.on_example_trained(&example_event(1, 0.2));
+        observer.on_neuron_trained(1, 0, &neuron_history(0.1));
+
+        let output = String::from_utf8(observer.writer.into_inner().unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("example_id,layer_id"));
+        assert!(lines[2].starts_with("0,0,1,1,"));
+        assert!(lines[3].starts_with("1,1,0,1,"));
+        let delta_norm: f64 = lines[3].rsplit(',').next().unwrap().parse().unwrap();
+        assert!((delta_norm - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "the logging interval must be positive")]
+    fn test_logging_observer_rejects_zero_interval() {
+        LoggingObserver::<f32>::new(0);
+    }
+}
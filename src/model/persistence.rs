@@ -0,0 +1,150 @@
+#[cfg(feature = "persistence")]
+use std::fs::{self, File};
+#[cfg(feature = "persistence")]
+use std::io::{self, BufReader, BufWriter};
+#[cfg(feature = "persistence")]
+use std::path::Path;
+#[cfg(feature = "persistence")]
+use std::process;
+#[cfg(feature = "persistence")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nalgebra::convert;
+#[cfg(feature = "persistence")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "persistence")]
+use crate::model::gln_model::GLN;
+use crate::model::config::{ContextSharing, Monotonicity, WeightProjection};
+use crate::utils::float::GLNFloat;
+
+// Plain representations of the model parameters, independent of the float type the
+// model is computed in. Drift detection is not part of the state and has to be configured
+// again after loading.
+
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct GLNState {
+    pub base_layer: BaseLayerState,
+    pub layers: Vec<LayerState>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub calibration: CalibrationState,
+}
+
+// Fitted parameters of a calibrator, which also determine its configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub enum CalibrationState {
+    #[default]
+    Disabled,
+    NegativeDownsampling { sampling_rate: f64 },
+    PlattScaling { learning_rate: f64, scale: f64, bias: f64 },
+    Isotonic { positives: Vec<f64>, counts: Vec<f64>, fitted: Vec<f64> },
+}
+
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct BaseLayerState {
+    pub pred_clipping_value: f64,
+    pub feature_dim: usize,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub monotone_inputs: Vec<bool>,
+}
+
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct LayerState {
+    pub input_dim: usize,
+    pub neurons: Vec<NeuronState>,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub context_sharing: ContextSharing,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub masked_features: Vec<usize>,
+}
+
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct NeuronState {
+    pub gate: GateState,
+    pub learning_rate: f64,
+    pub reg_param: f64,
+    pub positive_weight: f64,
+    pub negative_weight: f64,
+    pub pred_clipping_value: f64,
+    pub weight_clipping_value: f64,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub projection: WeightProjection,
+    #[cfg_attr(feature = "persistence", serde(default))]
+    pub input_monotonicity: Vec<Monotonicity>,
+}
+
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct GateState {
+    pub context: HalfSpaceContextState,
+    pub weights: Vec<Vec<f64>>,
+    pub initial_weights: Vec<Vec<f64>>,
+    pub step: usize,
+    pub last_updated: Vec<usize>,
+    pub visit_counts: Vec<usize>,
+    pub weight_decay: f64,
+    pub context_reset_steps: Option<usize>,
+}
+
+#[cfg_attr(feature = "persistence", derive(Serialize, Deserialize))]
+pub struct HalfSpaceContextState {
+    pub feature_dim: usize,
+    pub context_dim: usize,
+    pub context_maps: Vec<Vec<f64>>,
+    pub context_bias: Vec<f64>,
+}
+
+pub fn to_f64<T: GLNFloat>(value: T) -> f64 {
+    value.to_subset().unwrap()
+}
+
+pub fn from_f64<T: GLNFloat>(value: f64) -> T {
+    convert(value)
+}
+
+pub fn vec_to_f64<T: GLNFloat>(values: &[T]) -> Vec<f64> {
+    values.iter().map(|value| to_f64(*value)).collect()
+}
+
+pub fn vec_from_f64<T: GLNFloat>(values: &[f64]) -> Vec<T> {
+    values.iter().map(|value| from_f64(*value)).collect()
+}
+
+// Distinguishes the temporary files of concurrent saves within one process.
+#[cfg(feature = "persistence")]
+static NEXT_TEMP_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "persistence")]
+impl<T: GLNFloat> GLN<T> {
+    // Writes a temporary file next to `path` and renames it into place, so that a reader or a
+    // concurrent save never sees a partially written model.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            NEXT_TEMP_FILE_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+
+        let result = File::create(&temp_path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer(&mut writer, &self.to_state()).map_err(io::Error::from)?;
+            writer.into_inner().map_err(|error| error.into_error())?.sync_all()
+        });
+        match result.and_then(|()| fs::rename(&temp_path, path)) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(error)
+            }
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<GLN<T>> {
+        let reader = BufReader::new(File::open(path)?);
+        let state: GLNState = serde_json::from_reader(reader).map_err(io::Error::from)?;
+        Ok(GLN::from_state(&state))
+    }
+}
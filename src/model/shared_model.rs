@@ -0,0 +1,111 @@
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use nalgebra::DVector;
+
+use crate::model::gln_model::{GLNPrediction, PredictFitResult, GLN};
+use crate::utils::float::GLNFloat;
+
+pub struct GLNSnapshot<T: GLNFloat = f32> {
+    pub model: GLN<T>,
+    // Number of training examples applied to `model`.
+    pub num_updates: usize,
+}
+
+struct Trainer<T: GLNFloat> {
+    model: GLN<T>,
+    num_updates: usize,
+}
+
+// A GLN that can be shared between threads which predict and a thread that trains.
+//
+// Training is applied to a private copy of the model, serialized by a mutex. Every
+// `publish_interval` updates the copy is published as an immutable snapshot. Readers
+// only take a read lock to clone the `Arc` of the current snapshot, so they never wait
+// for a training step. Each prediction is computed on a single snapshot, which contains
+// exactly the first `num_updates` training examples and none of the later ones, and the
+// snapshots seen by a reader never go back in time.
+//
+// Examples whose features do not match the model are rejected before the training copy is
+// locked. If an update panics anyway, e.g. in an observer, the next update recovers the lock
+// and continues from the training copy, which may contain part of the failed update.
+pub struct SharedGLN<T: GLNFloat = f32> {
+    snapshot: RwLock<Arc<GLNSnapshot<T>>>,
+    trainer: Mutex<Trainer<T>>,
+    publish_interval: usize,
+}
+
+impl<T: GLNFloat> SharedGLN<T> {
+    pub fn new(model: GLN<T>, publish_interval: usize) -> Self {
+        SharedGLN {
+            snapshot: RwLock::new(Arc::new(GLNSnapshot {
+                model: model.clone(),
+                num_updates: 0,
+            })),
+            trainer: Mutex::new(Trainer {
+                model,
+                num_updates: 0,
+            }),
+            publish_interval: publish_interval.max(1),
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<GLNSnapshot<T>> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    pub fn predict(&self, features: &DVector<T>) -> GLNPrediction<T> {
+        self.snapshot().model.predict(features)
+    }
+
+    pub fn predict_fit(&self, features: &DVector<T>, target: i32) -> PredictFitResult<T> {
+        self.predict_fit_weighted(features, target, T::one())
+    }
+
+    pub fn predict_fit_weighted(
+        &self,
+        features: &DVector<T>,
+        target: i32,
+        sample_weight: T,
+    ) -> PredictFitResult<T> {
+        let feature_dim = self.snapshot().model.feature_dim();
+        assert_eq!(
+            features.len(),
+            feature_dim,
+            "expected {} features but got {}",
+            feature_dim,
+            features.len()
+        );
+        let mut trainer = self.lock_trainer();
+        let result = trainer
+            .model
+            .predict_fit_weighted(features, target, sample_weight);
+        trainer.num_updates += 1;
+
+        if trainer.num_updates.is_multiple_of(self.publish_interval) {
+            self.publish_locked(&trainer);
+        }
+        result
+    }
+
+    // Publishes the training copy immediately, regardless of `publish_interval`.
+    pub fn publish(&self) {
+        let trainer = self.lock_trainer();
+        self.publish_locked(&trainer);
+    }
+
+    fn lock_trainer(&self) -> MutexGuard<'_, Trainer<T>> {
+        self.trainer.lock().unwrap_or_else(|poisoned| {
+            self.trainer.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    fn publish_locked(&self, trainer: &Trainer<T>) {
+        // Clone outside of the write lock so that readers are only blocked by the pointer swap.
+        let snapshot = Arc::new(GLNSnapshot {
+            model: trainer.model.clone(),
+            num_updates: trainer.num_updates,
+        });
+        *self.snapshot.write().unwrap() = snapshot;
+    }
+}
@@ -1,59 +1,125 @@
+use nalgebra::convert;
+
+use crate::model::config::ClassWeights;
+use crate::utils::float::GLNFloat;
 use crate::utils::math;
 
-pub trait OnlineGradient {
+pub trait OnlineGradient<T: GLNFloat = f32> {
     fn calculate_grad(
         &self,
-        xs: &Vec<f32>,
+        xs: &[T],
+        target: i32,
+        weights: &[T],
+        index: usize,
+        clipping_value: T,
+    ) -> T;
+
+    fn calculate_grad_by_logits(
+        &self,
+        input_logits: &[T],
         target: i32,
-        weights: &Vec<f32>,
+        weights: &[T],
         index: usize,
-        clipping_value: f32,
-    ) -> f32;
+        sample_weight: T,
+    ) -> T;
 }
 
-pub struct LogGeometricMixingGradient {
-    reg_param: f32,
-    negative_weight: f32,
+#[derive(Clone)]
+pub struct LogGeometricMixingGradient<T: GLNFloat = f32> {
+    reg_param: T,
+    class_weights: ClassWeights<T>,
 }
 
-impl LogGeometricMixingGradient {
-    pub fn new(reg_param: f32, negative_weight: f32) -> Self {
+impl<T: GLNFloat> LogGeometricMixingGradient<T> {
+    pub fn new(reg_param: T, negative_weight: T) -> Self {
+        Self::with_class_weights(reg_param, ClassWeights::with_negative_weight(negative_weight))
+    }
+
+    pub fn with_class_weights(reg_param: T, class_weights: ClassWeights<T>) -> Self {
         LogGeometricMixingGradient {
-            reg_param: reg_param,
-            negative_weight: negative_weight,
+            reg_param,
+            class_weights,
         }
     }
+
+    pub fn reg_param(&self) -> T {
+        self.reg_param
+    }
+
+    pub fn class_weights(&self) -> ClassWeights<T> {
+        self.class_weights
+    }
+
+    pub fn set_class_weights(&mut self, class_weights: ClassWeights<T>) {
+        self.class_weights = class_weights;
+    }
+
+    // Class weighted log loss of the geometric mixing plus L2 regularization of the weights,
+    // the objective that `calculate_grad` differentiates. Evaluated in logit space, which stays
+    // accurate when the mixing is close to 0 or 1.
+    pub fn loss(&self, inputs: &[T], target: i32, weights: &[T], clipping_value: T) -> T {
+        let input_logits = inputs
+            .iter()
+            .map(|x| math::logit(math::clip_prob(*x, clipping_value)))
+            .collect::<Vec<T>>();
+        self.loss_by_logits(&input_logits, target, weights, T::one())
+    }
+
+    // Same objective for `calculate_grad_by_logits`, with the log loss scaled by `sample_weight`.
+    pub fn loss_by_logits(&self, input_logits: &[T], target: i32, weights: &[T], sample_weight: T) -> T {
+        sample_weight
+            * self.class_weights.weight(target)
+            * math::geometric_mixing_logit_loss(target, math::geometric_mixing_logit(input_logits, weights))
+            + self.regularization(weights)
+    }
+
+    fn regularization(&self, weights: &[T]) -> T {
+        let squared_norm = weights.iter().fold(T::zero(), |acc, weight| acc + *weight * *weight);
+        self.reg_param * squared_norm / convert(2.0)
+    }
 }
 
-impl OnlineGradient for LogGeometricMixingGradient {
+impl<T: GLNFloat> OnlineGradient<T> for LogGeometricMixingGradient<T> {
     fn calculate_grad(
         &self,
-        inputs: &Vec<f32>,
+        inputs: &[T],
         target: i32,
-        weights: &Vec<f32>,
+        weights: &[T],
         index: usize,
-        clipping_value: f32,
-    ) -> f32 {
-        if target == 1 {
-            (math::geometric_mixing(inputs, weights, clipping_value) - target as f32)
-                * math::logit(inputs[index])
-                + self.reg_param * weights[index]
-        } else {
-            self.negative_weight
-                * (math::geometric_mixing(inputs, weights, clipping_value) - target as f32)
-                * math::logit(inputs[index])
-                + self.reg_param * weights[index]
-        }
+        clipping_value: T,
+    ) -> T {
+        let target_value: T = convert(target as f64);
+        self.class_weights.weight(target)
+            * (math::geometric_mixing(inputs, weights, clipping_value) - target_value)
+            * math::logit(inputs[index])
+            + self.reg_param * weights[index]
+    }
+
+    fn calculate_grad_by_logits(
+        &self,
+        input_logits: &[T],
+        target: i32,
+        weights: &[T],
+        index: usize,
+        sample_weight: T,
+    ) -> T {
+        let target_value: T = convert(target as f64);
+        let error = math::sigmoid(math::geometric_mixing_logit(input_logits, weights)) - target_value;
+        sample_weight * self.class_weights.weight(target) * error * input_logits[index]
+            + self.reg_param * weights[index]
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::model::config::ClassWeights;
     use crate::optimize::grad::{LogGeometricMixingGradient, OnlineGradient};
+    use crate::optimize::gradient_check::GradientCheck;
+    use crate::utils::math::logit;
 
     #[test]
     fn test_log_geometric_mixing_gradient() {
-        let negative_weight = 1.0;
+        let negative_weight = 1.0_f32;
         let reg_param = 0.1;
         let grad = LogGeometricMixingGradient::new(reg_param, negative_weight);
         let xs = vec![0.1, 0.4, 0.6];
@@ -64,4 +130,54 @@ mod test {
         let actual = grad.calculate_grad(&xs, target, &weights, index, clipping_value);
         assert_eq!(actual, 0.44013876);
     }
+
+    #[test]
+    fn test_log_geometric_mixing_gradient_by_logits() {
+        let grad = LogGeometricMixingGradient::new(0.1_f32, 1.0);
+        let xs: Vec<f32> = vec![0.1, 0.4, 0.6];
+        let input_logits = xs.iter().map(|x| logit(*x)).collect::<Vec<f32>>();
+        let weights = vec![0.2, 1.6, 0.7];
+        let expected = grad.calculate_grad(&xs, 1, &weights, 1, 1.0e-3);
+        let actual = grad.calculate_grad_by_logits(&input_logits, 1, &weights, 1, 1.0);
+        assert!((actual - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_log_geometric_mixing_gradient_with_class_weights() {
+        let reg_param = 0.1_f32;
+        let input_logits = vec![-0.5, 0.4, 1.2];
+        let weights = vec![0.2, 1.6, 0.7];
+        let negative_weight_grad = LogGeometricMixingGradient::new(reg_param, 3.0);
+        let class_weight_grad =
+            LogGeometricMixingGradient::with_class_weights(reg_param, ClassWeights::new(1.0, 3.0));
+        let sample_weight_grad = LogGeometricMixingGradient::new(reg_param, 1.0);
+
+        let expected = negative_weight_grad.calculate_grad_by_logits(&input_logits, 0, &weights, 2, 1.0);
+        let by_class = class_weight_grad.calculate_grad_by_logits(&input_logits, 0, &weights, 2, 1.0);
+        let by_sample = sample_weight_grad.calculate_grad_by_logits(&input_logits, 0, &weights, 2, 3.0);
+        assert_eq!(by_class, expected);
+        assert!((by_sample - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_log_geometric_mixing_gradient_matches_finite_differences() {
+        for (reg_param, class_weights) in vec![
+            (0.0, ClassWeights::new(1.0, 1.0)),
+            (0.1, ClassWeights::new(1.0, 1.0)),
+            (0.05, ClassWeights::new(0.5, 3.0)),
+        ] {
+            let grad = LogGeometricMixingGradient::<f64>::with_class_weights(reg_param, class_weights);
+            let check = GradientCheck::default();
+            check
+                .check(&grad, |inputs, target, weights, clipping_value| {
+                    grad.loss(inputs, target, weights, clipping_value)
+                })
+                .unwrap();
+            check
+                .check_by_logits(&grad, |input_logits, target, weights, sample_weight| {
+                    grad.loss_by_logits(input_logits, target, weights, sample_weight)
+                })
+                .unwrap();
+        }
+    }
 }
@@ -0,0 +1,165 @@
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::optimize::grad::OnlineGradient;
+
+// Compares an `OnlineGradient` with central finite differences of the loss it claims to
+// differentiate, over random inputs, weights, targets and sample weights. Computed in f64 so
+// that the differences are accurate.
+pub struct GradientCheck {
+    pub num_trials: usize,
+    pub max_input_dim: usize,
+    pub step: f64,
+    // Allowed difference relative to the larger of 1 and the numeric derivative.
+    pub tolerance: f64,
+    pub seed: u64,
+}
+
+impl Default for GradientCheck {
+    fn default() -> Self {
+        GradientCheck {
+            num_trials: 200,
+            max_input_dim: 8,
+            step: 1e-6,
+            tolerance: 1e-5,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GradientMismatch {
+    pub inputs: Vec<f64>,
+    pub target: i32,
+    pub weights: Vec<f64>,
+    pub index: usize,
+    pub analytic: f64,
+    pub numeric: f64,
+}
+
+impl fmt::Display for GradientMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gradient of weight {} is {} but the finite difference is {} (inputs {:?}, target {}, weights {:?})",
+            self.index, self.analytic, self.numeric, self.inputs, self.target, self.weights
+        )
+    }
+}
+
+impl GradientCheck {
+    // Checks `calculate_grad` against `loss(inputs, target, weights, clipping_value)` with
+    // probability inputs inside the clipping range.
+    pub fn check<G, L>(&self, gradient: &G, loss: L) -> Result<(), GradientMismatch>
+    where
+        G: OnlineGradient<f64>,
+        L: Fn(&[f64], i32, &[f64], f64) -> f64,
+    {
+        let clipping_value = 1e-3;
+        self.run(
+            |rng| rng.gen_range(0.01..0.99),
+            |inputs, target, weights, index, _| gradient.calculate_grad(inputs, target, weights, index, clipping_value),
+            |inputs, target, weights, _| loss(inputs, target, weights, clipping_value),
+        )
+    }
+
+    // Checks `calculate_grad_by_logits` against `loss(input_logits, target, weights, sample_weight)`.
+    pub fn check_by_logits<G, L>(&self, gradient: &G, loss: L) -> Result<(), GradientMismatch>
+    where
+        G: OnlineGradient<f64>,
+        L: Fn(&[f64], i32, &[f64], f64) -> f64,
+    {
+        self.run(
+            |rng| rng.gen_range(-4.0..4.0),
+            |input_logits, target, weights, index, sample_weight| {
+                gradient.calculate_grad_by_logits(input_logits, target, weights, index, sample_weight)
+            },
+            loss,
+        )
+    }
+
+    fn run<I, G, L>(&self, sample_input: I, gradient: G, loss: L) -> Result<(), GradientMismatch>
+    where
+        I: Fn(&mut ChaCha8Rng) -> f64,
+        G: Fn(&[f64], i32, &[f64], usize, f64) -> f64,
+        L: Fn(&[f64], i32, &[f64], f64) -> f64,
+    {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        for _ in 0..self.num_trials {
+            let input_dim = rng.gen_range(1..=self.max_input_dim);
+            let inputs: Vec<f64> = (0..input_dim).map(|_| sample_input(&mut rng)).collect();
+            let weights: Vec<f64> = (0..input_dim).map(|_| rng.gen_range(-2.0..2.0)).collect();
+            let target = rng.gen_range(0..=1);
+            let sample_weight = rng.gen_range(0.1..3.0);
+
+            for index in 0..input_dim {
+                let mut shifted = weights.clone();
+                shifted[index] = weights[index] + self.step;
+                let upper = loss(&inputs, target, &shifted, sample_weight);
+                shifted[index] = weights[index] - self.step;
+                let lower = loss(&inputs, target, &shifted, sample_weight);
+
+                let numeric = (upper - lower) / (2.0 * self.step);
+                let analytic = gradient(&inputs, target, &weights, index, sample_weight);
+                if (analytic - numeric).abs() > self.tolerance * numeric.abs().max(1.0) {
+                    return Err(GradientMismatch {
+                        inputs,
+                        target,
+                        weights,
+                        index,
+                        analytic,
+                        numeric,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::optimize::grad::{LogGeometricMixingGradient, OnlineGradient};
+    use crate::optimize::gradient_check::GradientCheck;
+
+    // Drops the regularization term of the gradient it wraps.
+    struct UnregularizedGradient(LogGeometricMixingGradient<f64>);
+
+    impl OnlineGradient<f64> for UnregularizedGradient {
+        fn calculate_grad(&self, xs: &[f64], target: i32, weights: &[f64], index: usize, clipping_value: f64) -> f64 {
+            self.0.calculate_grad(xs, target, weights, index, clipping_value) - self.0.reg_param() * weights[index]
+        }
+
+        fn calculate_grad_by_logits(
+            &self,
+            input_logits: &[f64],
+            target: i32,
+            weights: &[f64],
+            index: usize,
+            sample_weight: f64,
+        ) -> f64 {
+            self.0.calculate_grad_by_logits(input_logits, target, weights, index, sample_weight)
+                - self.0.reg_param() * weights[index]
+        }
+    }
+
+    #[test]
+    fn test_gradient_check_detects_a_wrong_gradient() {
+        let grad = LogGeometricMixingGradient::new(0.5, 1.0);
+        let wrong = UnregularizedGradient(grad.clone());
+        let check = GradientCheck::default();
+
+        let mismatch = check
+            .check_by_logits(&wrong, |input_logits, target, weights, sample_weight| {
+                grad.loss_by_logits(input_logits, target, weights, sample_weight)
+            })
+            .unwrap_err();
+        assert!((mismatch.analytic - mismatch.numeric + 0.5 * mismatch.weights[mismatch.index]).abs() < 1e-4);
+        assert!(mismatch.to_string().starts_with("gradient of weight"));
+        assert!(check
+            .check(&wrong, |inputs, target, weights, clipping_value| grad.loss(inputs, target, weights, clipping_value))
+            .is_err());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod grad;
+pub mod gradient_check;
 pub mod optimizer;
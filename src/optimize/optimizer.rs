@@ -1,15 +1,22 @@
-pub struct OnlineGradientDecent {
-    learning_rate: f32,
+use crate::utils::float::GLNFloat;
+
+#[derive(Clone)]
+pub struct OnlineGradientDecent<T: GLNFloat = f32> {
+    learning_rate: T,
 }
 
-impl OnlineGradientDecent {
-    pub fn new(learning_rate: f32) -> Self {
+impl<T: GLNFloat> OnlineGradientDecent<T> {
+    pub fn new(learning_rate: T) -> Self {
         OnlineGradientDecent {
             learning_rate: learning_rate,
         }
     }
 
-    pub fn update(&self, weight: f32, grad: f32) -> f32 {
+    pub fn learning_rate(&self) -> T {
+        self.learning_rate
+    }
+
+    pub fn update(&self, weight: T, grad: T) -> T {
         weight - self.learning_rate * grad
     }
 }
@@ -0,0 +1,226 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use nalgebra::DVector;
+use serde::{Deserialize, Serialize};
+
+use crate::model::shared_model::SharedGLN;
+
+// Largest request body that is read, well above a JSON feature vector of any practical size.
+const MAX_BODY_SIZE: usize = 1 << 20;
+
+#[derive(Deserialize)]
+struct PredictRequest {
+    features: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct PredictResponse {
+    probability: f32,
+    raw_probability: f32,
+}
+
+#[derive(Deserialize)]
+struct FeedbackRequest {
+    features: Vec<f32>,
+    target: i32,
+    #[serde(default)]
+    weight: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct FeedbackResponse {
+    prediction: f32,
+    loss: f32,
+}
+
+#[derive(Serialize)]
+struct SnapshotResponse {
+    path: String,
+    num_updates: usize,
+}
+
+#[derive(Serialize, Default, Clone)]
+pub struct ServerMetrics {
+    pub num_predictions: usize,
+    pub num_feedbacks: usize,
+    // Progressive validation metrics of the predictions made before each feedback update.
+    pub mean_loss: f32,
+    pub accuracy: f32,
+}
+
+impl ServerMetrics {
+    fn record_feedback(&mut self, loss: f32, correct: bool) {
+        self.num_feedbacks += 1;
+        let count = self.num_feedbacks as f32;
+        self.mean_loss += (loss - self.mean_loss) / count;
+        self.accuracy += ((correct as i32) as f32 - self.accuracy) / count;
+    }
+}
+
+pub struct ServerState {
+    model: SharedGLN,
+    snapshot_path: PathBuf,
+    metrics: Mutex<ServerMetrics>,
+}
+
+impl ServerState {
+    pub fn new(model: SharedGLN, snapshot_path: PathBuf) -> Self {
+        ServerState {
+            model,
+            snapshot_path,
+            metrics: Mutex::new(ServerMetrics::default()),
+        }
+    }
+
+    pub fn metrics(&self) -> ServerMetrics {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    // The model panics on feature vectors of any other length, so they are rejected up front.
+    fn check_features(&self, features: &[f32]) -> Result<(), String> {
+        let feature_dim = self.model.snapshot().model.feature_dim();
+        if features.is_empty() {
+            Err("features must not be empty".to_string())
+        } else if features.len() == feature_dim {
+            Ok(())
+        } else {
+            Err(format!("expected {} features but got {}", feature_dim, features.len()))
+        }
+    }
+
+    fn feedback(&self, request: FeedbackRequest) -> (u16, String) {
+        let features = DVector::from_vec(request.features);
+        let result = self
+            .model
+            .predict_fit_weighted(&features, request.target, request.weight.unwrap_or(1.0));
+        let output_layer = result.loss_histories.len() - 1;
+        let loss = result.loss_histories[&output_layer][&0];
+        let correct = (result.prediction > 0.5) == (request.target == 1);
+        self.metrics.lock().unwrap().record_feedback(loss, correct);
+        json_response(&FeedbackResponse {
+            prediction: result.prediction,
+            loss,
+        })
+    }
+
+    fn handle(&self, method: &str, path: &str, body: &[u8]) -> (u16, String) {
+        match (method, path) {
+            ("POST", "/predict") => match serde_json::from_slice::<PredictRequest>(body) {
+                Ok(request) => match self.check_features(&request.features) {
+                    Ok(()) => {
+                        let prediction = self.model.predict(&DVector::from_vec(request.features));
+                        self.metrics.lock().unwrap().num_predictions += 1;
+                        json_response(&PredictResponse {
+                            probability: prediction.probability,
+                            raw_probability: prediction.raw_probability,
+                        })
+                    }
+                    Err(message) => error_response(400, &message),
+                },
+                Err(error) => error_response(400, &error.to_string()),
+            },
+            ("POST", "/feedback") => match serde_json::from_slice::<FeedbackRequest>(body) {
+                Ok(request) if request.target == 0 || request.target == 1 => {
+                    match self.check_features(&request.features) {
+                        Ok(()) => self.feedback(request),
+                        Err(message) => error_response(400, &message),
+                    }
+                }
+                Ok(_) => error_response(400, "target must be 0 or 1"),
+                Err(error) => error_response(400, &error.to_string()),
+            },
+            ("GET", "/metrics") => json_response(&self.metrics()),
+            ("POST", "/snapshot") => {
+                self.model.publish();
+                let snapshot = self.model.snapshot();
+                match snapshot.model.save(&self.snapshot_path) {
+                    Ok(()) => json_response(&SnapshotResponse {
+                        path: self.snapshot_path.display().to_string(),
+                        num_updates: snapshot.num_updates,
+                    }),
+                    Err(error) => error_response(500, &error.to_string()),
+                }
+            }
+            _ => error_response(404, "not found"),
+        }
+    }
+}
+
+// Accepts connections until the listener fails, handling each one on its own thread.
+// Every connection carries a single request and is closed after the response.
+pub fn serve(listener: TcpListener, state: Arc<ServerState>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            if let Err(error) = handle_connection(stream, &state) {
+                eprintln!("failed to handle a request: {}", error);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, state: &ServerState) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        let message = format!("request body is larger than {} bytes", MAX_BODY_SIZE);
+        return write_response(stream, 413, &error_response(413, &message).1);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (status, response_body) = state.handle(&method, &path, &body);
+    write_response(stream, status, &response_body)
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn json_response<S: Serialize>(value: &S) -> (u16, String) {
+    (200, serde_json::to_string(value).unwrap())
+}
+
+fn error_response(status: u16, message: &str) -> (u16, String) {
+    (status, serde_json::json!({ "error": message }).to_string())
+}
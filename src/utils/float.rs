@@ -0,0 +1,22 @@
+use nalgebra::RealField;
+
+/// Floating-point type the model can be computed in.
+///
+/// Constants that depend on the precision live here so that `f64` models
+/// are not held back by values that were chosen for `f32`.
+pub trait GLNFloat: RealField + Copy {
+    /// Default distance from 0 and 1 at which predictions are clipped.
+    const PRED_CLIPPING_VALUE: Self;
+    /// Distance from 0 and 1 at which `logit` clamps its argument to stay finite.
+    const LOGIT_EPSILON: Self;
+}
+
+impl GLNFloat for f32 {
+    const PRED_CLIPPING_VALUE: f32 = 1e-3;
+    const LOGIT_EPSILON: f32 = f32::EPSILON;
+}
+
+impl GLNFloat for f64 {
+    const PRED_CLIPPING_VALUE: f64 = 1e-6;
+    const LOGIT_EPSILON: f64 = f64::EPSILON;
+}
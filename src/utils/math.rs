@@ -1,44 +1,87 @@
-pub fn logit(value: f32) -> f32 {
-    if value >= 1.0 || value <= 0.0 {
-        panic!("logit takes invalid value: {}", value);
+use nalgebra::convert;
+
+use crate::utils::float::GLNFloat;
+
+pub fn logit<T: GLNFloat>(value: T) -> T {
+    // Exactly 0 or 1 would give an infinite logit.
+    let value = clip_prob(value, T::LOGIT_EPSILON);
+    (value / (T::one() - value)).ln()
+}
+
+pub fn sigmoid<T: GLNFloat>(value: T) -> T {
+    if value >= T::zero() {
+        T::one() / (T::one() + (-value).exp())
+    } else {
+        // exp(-value) overflows for large negative values, so use the equivalent form.
+        let exp_value = value.exp();
+        exp_value / (T::one() + exp_value)
     }
-    (value / (1.0 - value)).ln()
 }
 
-pub fn sigmoid(value: f32) -> f32 {
-    1.0 / (1.0 + (-value).exp())
+/// log(1 + exp(value)) without overflow for large positive values.
+pub fn softplus<T: GLNFloat>(value: T) -> T {
+    value.max(T::zero()) + (-value.max(-value)).exp().ln_1p()
+}
+
+/// log(sigmoid(value)) without underflow for large negative values.
+pub fn log_sigmoid<T: GLNFloat>(value: T) -> T {
+    -softplus(-value)
 }
 
-pub fn geometric_mixing(probabilities: &Vec<f32>, weights: &Vec<f32>, clipping_value: f32) -> f32 {
-    let weight_multiplied_logits = weights
+/// Clips a logit to the range that `clip_prob` allows in probability space.
+pub fn clip_logit<T: GLNFloat>(value: T, clipping_value: T) -> T {
+    clip_hypercube(value, logit(T::one() - clipping_value))
+}
+
+pub fn geometric_mixing<T: GLNFloat>(probabilities: &[T], weights: &[T], clipping_value: T) -> T {
+    let logits = probabilities
+        .iter()
+        .map(|p| logit(clip_prob(*p, clipping_value)))
+        .collect::<Vec<T>>();
+    sigmoid(geometric_mixing_logit(&logits, weights))
+}
+
+/// Geometric mixing in logit space, i.e. the logit of `geometric_mixing`.
+pub fn geometric_mixing_logit<T: GLNFloat>(logits: &[T], weights: &[T]) -> T {
+    weights
         .iter()
-        .zip(probabilities)
-        .map(|(w, p)| w * logit(clip_prob(*p, clipping_value)))
-        .sum();
-    sigmoid(weight_multiplied_logits)
+        .zip(logits)
+        .map(|(w, l)| *w * *l)
+        .fold(T::zero(), |acc, value| acc + value)
 }
 
-pub fn geometric_mixing_loss(target: i32, geo: f32) -> f32 {
+pub fn geometric_mixing_loss<T: GLNFloat>(target: i32, geo: T) -> T {
     if target == 1 {
         -geo.ln()
     } else if target == 0 {
-        -(1.0 - geo).ln()
+        -(T::one() - geo).ln()
     } else {
         panic!("invalid target value: {:?}", target);
     }
 }
 
-pub fn clip_prob(value: f32, epsilon: f32) -> f32 {
-    if value >= (1.0 - epsilon) {
-        1.0 - epsilon
-    } else if value <= (0.0 + epsilon) {
+/// Log loss of a geometric mixing given as a logit.
+pub fn geometric_mixing_logit_loss<T: GLNFloat>(target: i32, geo_logit: T) -> T {
+    if target == 1 {
+        -log_sigmoid(geo_logit)
+    } else if target == 0 {
+        -log_sigmoid(-geo_logit)
+    } else {
+        panic!("invalid target value: {:?}", target);
+    }
+}
+
+pub fn clip_prob<T: GLNFloat>(value: T, epsilon: T) -> T {
+    if value >= (T::one() - epsilon) {
+        T::one() - epsilon
+    } else if value <= (T::zero() + epsilon) {
         epsilon
     } else {
         value
     }
 }
 
-pub fn clip_hypercube(value: f32, edge: f32) -> f32 {
+pub fn clip_hypercube<T: GLNFloat>(value: T, edge: T) -> T {
     if value >= edge {
         edge
     } else if value <= -edge {
@@ -48,29 +91,134 @@ pub fn clip_hypercube(value: f32, edge: f32) -> f32 {
     }
 }
 
-pub fn norm(vector: &Vec<f32>) -> f32 {
-    let inner_product_itself: f32 = vector.iter().map(|ele| ele.powf(2.0)).sum();
+// Rescales `weights` onto the Euclidean ball of `radius` if they lie outside it.
+pub fn project_l2_ball<T: GLNFloat>(weights: &mut [T], radius: T) {
+    let norm = weights.iter().fold(T::zero(), |acc, weight| acc + *weight * *weight).sqrt();
+    if norm > radius {
+        for weight in weights.iter_mut() {
+            *weight *= radius / norm;
+        }
+    }
+}
+
+// Euclidean projection onto the probability simplex by sorting (Duchi et al., 2008).
+pub fn project_simplex<T: GLNFloat>(weights: &mut [T]) {
+    let mut sorted = weights.to_vec();
+    sorted.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+    let mut cumulative_sum = T::zero();
+    let mut threshold = T::zero();
+    for (index, value) in sorted.iter().enumerate() {
+        cumulative_sum += *value;
+        let candidate = (cumulative_sum - T::one()) / convert((index + 1) as f64);
+        if *value > candidate {
+            threshold = candidate;
+        }
+    }
+    for weight in weights.iter_mut() {
+        *weight = (*weight - threshold).max(T::zero());
+    }
+}
+
+pub fn norm<T: GLNFloat>(vector: &Vec<T>) -> T {
+    let inner_product_itself = vector
+        .iter()
+        .fold(T::zero(), |acc, ele| acc + ele.powi(2));
     inner_product_itself.sqrt()
 }
 
-pub fn calibration(original_prob: f32, sampling_rate: f32) -> f32 {
-    original_prob / (original_prob + (1.0 - original_prob) / sampling_rate)
+pub fn calibration<T: GLNFloat>(original_prob: T, sampling_rate: T) -> T {
+    original_prob / (original_prob + (T::one() - original_prob) / sampling_rate)
 }
 
-pub fn accuracy(predictions: &Vec<f32>, labels: &Vec<i32>) -> f32 {
-    let mut numerator = 0.0_f32;
+pub fn accuracy<T: GLNFloat>(predictions: &Vec<T>, labels: &Vec<i32>) -> T {
+    let threshold: T = convert(0.5);
+    let mut numerator = T::zero();
     for (pred, label) in predictions.iter().zip(labels) {
-        if *pred > 0.5 {
+        if *pred > threshold {
             if *label == 1 {
-                numerator += 1.0;
+                numerator += T::one();
             }
         } else {
             if *label == 0 {
-                numerator += 1.0;
+                numerator += T::one();
             }
         }
     }
-    numerator / (predictions.len() as f32)
+    numerator / convert(predictions.len() as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::math::{
+        geometric_mixing, geometric_mixing_logit, geometric_mixing_logit_loss,
+        geometric_mixing_loss, log_sigmoid, logit, project_l2_ball, project_simplex, sigmoid,
+        softplus,
+    };
+
+    #[test]
+    fn test_project_l2_ball() {
+        let mut weights = vec![3.0_f64, 4.0];
+        project_l2_ball(&mut weights, 1.0);
+        assert!((weights[0] - 0.6).abs() < 1e-12);
+        assert!((weights[1] - 0.8).abs() < 1e-12);
+
+        let mut inside = vec![0.3_f64, -0.4];
+        project_l2_ball(&mut inside, 1.0);
+        assert_eq!(inside, vec![0.3, -0.4]);
+    }
+
+    #[test]
+    fn test_project_simplex() {
+        let mut weights = vec![0.5_f64, 1.2, -0.3];
+        project_simplex(&mut weights);
+        assert!((weights[0] - 0.15).abs() < 1e-12);
+        assert!((weights[1] - 0.85).abs() < 1e-12);
+        assert_eq!(weights[2], 0.0);
+
+        // Points on the simplex stay where they are.
+        let mut on_simplex = vec![0.2_f64, 0.3, 0.5];
+        project_simplex(&mut on_simplex);
+        assert!(on_simplex.iter().zip(&[0.2, 0.3, 0.5]).all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_logit_at_bounds() {
+        assert!(logit(0.0_f32).is_finite());
+        assert!(logit(1.0_f32).is_finite());
+        assert_eq!(logit(0.0_f64), -logit(1.0_f64));
+    }
+
+    #[test]
+    fn test_sigmoid_large_negative() {
+        assert!(sigmoid(-100.0_f32) > 0.0);
+        assert_eq!(sigmoid(-1000.0_f32), 0.0);
+        assert_eq!(sigmoid(1000.0_f32), 1.0);
+    }
+
+    #[test]
+    fn test_softplus_and_log_sigmoid() {
+        assert_eq!(softplus(1000.0_f32), 1000.0);
+        assert_eq!(log_sigmoid(-1000.0_f32), -1000.0);
+        assert!((softplus(0.0_f64) - 2.0_f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_geometric_mixing_logit_matches_probability_space() {
+        let probabilities: Vec<f64> = vec![0.3, 0.2, 0.7];
+        let weights = vec![0.3, 0.5, 0.2];
+        let logits = probabilities.iter().map(|p| logit(*p)).collect::<Vec<f64>>();
+
+        let geo = geometric_mixing(&probabilities, &weights, 1e-3);
+        let geo_logit = geometric_mixing_logit(&logits, &weights);
+
+        assert!((sigmoid(geo_logit) - geo).abs() < 1e-12);
+        for target in [0, 1] {
+            let expected = geometric_mixing_loss(target, geo);
+            let actual = geometric_mixing_logit_loss(target, geo_logit);
+            assert!((actual - expected).abs() < 1e-12);
+        }
+    }
 }
 
 // #[cfg(test)]
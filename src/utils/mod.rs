@@ -1,2 +1,3 @@
 pub mod data_type;
+pub mod float;
 pub mod math;
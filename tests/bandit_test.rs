@@ -0,0 +1,55 @@
+use gln::bandit::glcb::{ExplorationStrategy, GLCB};
+use gln::model::gln_model::GLN;
+use nalgebra::DVector;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// Arm 0 pays off when the first feature is positive, arm 2 otherwise, arm 1 never does well.
+fn reward_probability(context: &DVector<f32>, arm: usize) -> f32 {
+    let probabilities = if context[0] > 0.0 {
+        [0.8, 0.3, 0.2]
+    } else {
+        [0.2, 0.3, 0.8]
+    };
+    probabilities[arm]
+}
+
+fn run_simulation(mut policy: GLCB, rounds: usize) -> f32 {
+    let mut rng = ChaCha8Rng::seed_from_u64(7);
+    let mut late_rewards = 0;
+    for round in 0..rounds {
+        let context = DVector::from_vec(vec![
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        ]);
+        let arm = policy.select(&context).arm;
+        let reward = (rng.gen::<f32>() < reward_probability(&context, arm)) as i32;
+        policy.update(arm, reward);
+
+        if round >= rounds - 500 {
+            late_rewards += reward;
+        }
+    }
+    late_rewards as f32 / 500.0
+}
+
+fn arm_model(feature_dim: usize) -> GLN {
+    GLN::new(vec![8, 4, 1], 4, feature_dim, 0.01, 5.0, 1.0, 0.0)
+}
+
+#[test]
+fn test_glcb_ucb_per_arm_models() {
+    let models = (0..3).map(|_| arm_model(3)).collect();
+    let policy = GLCB::new(models, ExplorationStrategy::Ucb { exploration: 0.2 }, 1);
+
+    // A uniformly random policy earns about 0.43.
+    assert!(run_simulation(policy, 3000) > 0.6);
+}
+
+#[test]
+fn test_glcb_thompson_shared_model() {
+    let policy = GLCB::with_shared_model(arm_model(6), 3, ExplorationStrategy::Thompson, 1);
+
+    assert!(run_simulation(policy, 3000) > 0.6);
+}
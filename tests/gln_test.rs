@@ -1,7 +1,22 @@
 use std::collections::HashMap;
 
+use gln::model::adaptation::ContextAdaptationConfig;
+use gln::model::calibration::CalibrationConfig;
+use gln::model::config::{
+    ClassWeights, ContextSharing, ForgettingConfig, Monotonicity, WeightInitialization,
+    WeightProjection,
+};
+use gln::model::context_func::{
+    CategoricalContext, ConcatContext, FixedContext, HalfSpaceContext, HyperplaneSampling,
+};
+use gln::model::drift::DriftDetectorConfig;
 use gln::model::gln_model;
+use gln::model::gln_model::MergeError;
+use gln::model::neuron::NeuronTrainHistory;
+use gln::model::observer::{DiagnosticsObserver, MetricsObserver, TrainObserver};
 use nalgebra::DVector;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use gln::utils::math::accuracy;
 
@@ -17,7 +32,7 @@ fn test_gln_predict() {
     let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
     let target = 1;
 
-    let mut gln = gln_model::GLN::new(
+    let mut gln: gln_model::GLN = gln_model::GLN::new(
         neuron_nums,
         context_dim,
         feature_dim,
@@ -53,7 +68,7 @@ fn test_gln_predict_fit() {
     let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
     let target = 1;
 
-    let mut gln = gln_model::GLN::new(
+    let mut gln: gln_model::GLN = gln_model::GLN::new(
         neuron_nums,
         context_dim,
         feature_dim,
@@ -94,7 +109,7 @@ fn test_gln_predict_with_zero_vector() {
 
     let label = 0;
 
-    let mut gln = gln_model::GLN::new(
+    let mut gln: gln_model::GLN = gln_model::GLN::new(
         neuron_nums,
         context_dim,
         feature_dim,
@@ -113,3 +128,573 @@ fn test_gln_predict_with_zero_vector() {
 
     // assert_eq!(pred.probability, 0.5000011);
 }
+
+#[test]
+fn test_gln_predict_fit_f64() {
+    let neuron_nums = vec![3, 2, 1];
+    let context_dim = 5;
+    let learning_rate = 0.1;
+    let weight_clipping_value = 5.0;
+    let feature_dim = 3;
+    let grad_weight = 1.0;
+
+    let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
+    let target = 1;
+
+    let mut gln = gln_model::GLN::<f64>::new(
+        neuron_nums,
+        context_dim,
+        feature_dim,
+        learning_rate,
+        weight_clipping_value,
+        grad_weight,
+        1.0,
+    );
+    let predict_fit_result = gln.predict_fit(&feature_vec, target);
+
+    for layer_losses in predict_fit_result.loss_histories.values() {
+        for loss in layer_losses.values() {
+            assert!((loss - 2.0_f64.ln()).abs() < 1e-9);
+        }
+    }
+    assert!((predict_fit_result.prediction - 0.5).abs() < 1e-9);
+}
+
+#[test]
+fn test_gln_predict_with_negative_downsampling_calibration() {
+    let neuron_nums = vec![3, 2, 1];
+    let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
+    let sampling_rate = 0.1;
+
+    let gln: gln_model::GLN = gln_model::GLN::new(neuron_nums, 5, 3, 0.1, 5.0, 1.0, 1.0)
+        .with_calibration(CalibrationConfig::NegativeDownsampling { sampling_rate });
+    let pred = gln.predict(&feature_vec);
+
+    let raw = pred.raw_probability;
+    assert_eq!(pred.probability, raw / (raw + (1.0 - raw) / sampling_rate));
+    assert!(pred.probability < pred.raw_probability);
+}
+
+#[test]
+fn test_gln_predict_fit_weighted() {
+    let neuron_nums = vec![3, 2, 1];
+    let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
+    let sample_weight = 2.5;
+
+    let mut gln: gln_model::GLN = gln_model::GLN::new(neuron_nums, 5, 3, 0.1, 5.0, 1.0, 1.0)
+        .with_class_weights(ClassWeights::new(1.0, 4.0));
+    let predict_fit_result = gln.predict_fit_weighted(&feature_vec, 1, sample_weight);
+
+    for layer_losses in predict_fit_result.loss_histories.values() {
+        for loss in layer_losses.values() {
+            assert_eq!(*loss, sample_weight * 0.69314504);
+        }
+    }
+}
+
+#[test]
+fn test_gln_detects_concept_drift() {
+    let neuron_nums = vec![4, 1];
+    let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
+
+    let mut gln: gln_model::GLN = gln_model::GLN::new(neuron_nums, 2, 3, 0.1, 5.0, 1.0, 0.0)
+        .with_forgetting(ForgettingConfig {
+            weight_decay: 1e-4,
+            context_reset_steps: Some(1000),
+        })
+        .with_drift_detection(
+            DriftDetectorConfig::PageHinkley {
+                delta: 0.01,
+                threshold: 5.0,
+                min_samples: 50,
+            },
+            1.0,
+        );
+
+    for _ in 0..300 {
+        let result = gln.predict_fit(&feature_vec, 1);
+        assert!(!result.drift_detected);
+    }
+    let detected = (0..300).any(|_| gln.predict_fit(&feature_vec, 0).drift_detected);
+    assert!(detected);
+}
+
+#[test]
+fn test_gln_predict_with_uncertainty() {
+    let neuron_nums = vec![3, 2, 1];
+    let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
+
+    let mut gln: gln_model::GLN = gln_model::GLN::new(neuron_nums, 5, 3, 0.1, 5.0, 1.0, 1.0);
+
+    let before = gln.predict_with_uncertainty(&feature_vec).uncertainty.unwrap();
+    assert_eq!(before.layer_uncertainties, vec![1.0, 1.0, 1.0]);
+    assert_eq!(before.aggregate, 1.0);
+    assert_eq!(before.disagreement, 0.0);
+
+    for _ in 0..3 {
+        gln.predict_fit(&feature_vec, 1);
+    }
+    let after = gln.predict_with_uncertainty(&feature_vec);
+    assert!(gln.predict(&feature_vec).uncertainty.is_none());
+    assert_eq!(after.uncertainty.unwrap().aggregate, 0.5);
+}
+
+#[test]
+fn test_gln_explain() {
+    let neuron_nums = vec![4, 3, 1];
+    let feature_vec = DVector::from_vec(vec![0.2, 0.7, 0.1, 0.4]);
+
+    let mut gln: gln_model::GLN<f64> = gln_model::GLN::new(neuron_nums, 3, 4, 0.1, 5.0, 1.0, 0.0);
+    for target in [1, 0, 1, 1, 0] {
+        gln.predict_fit(&feature_vec, target);
+    }
+
+    let explanation = gln.explain(&feature_vec);
+    let prediction = gln.predict(&feature_vec);
+
+    let total: f64 = explanation.contributions.iter().sum();
+    assert!((total - explanation.output_logit).abs() < 1e-12);
+    assert!((1.0 / (1.0 + (-total).exp()) - prediction.raw_probability).abs() < 1e-12);
+    assert_eq!(explanation.context_index_map, prediction.context_index_map);
+    for (layer_id, layer_bits) in explanation.context_bits.iter() {
+        for (neuron_id, bits) in layer_bits.iter() {
+            assert_eq!(bits.len(), 3);
+            let index: usize = bits.iter().enumerate().map(|(i, bit)| (*bit as usize) << i).sum();
+            assert_eq!(index, explanation.context_index_map[*layer_id][*neuron_id]);
+        }
+    }
+}
+
+#[test]
+fn test_gln_merge() {
+    let build = |seed| -> gln_model::GLN<f64> {
+        gln_model::GLN::new(vec![4, 2, 1], 3, 3, 0.1, 5.0, 1.0, 0.0).with_seed(seed)
+    };
+    let feature_vec = DVector::from_vec(vec![0.2, 0.3, 0.1]);
+
+    let mut first = build(11);
+    let mut second = build(11);
+    for _ in 0..5 {
+        first.predict_fit(&feature_vec, 1);
+    }
+    for _ in 0..15 {
+        second.predict_fit(&feature_vec, 0);
+    }
+    let first_logit = first.explain(&feature_vec).output_logit;
+    let second_logit = second.explain(&feature_vec).output_logit;
+
+    let models = vec![first, second];
+    assert_eq!(gln_model::GLN::check_merge_compatibility(&models), Ok(()));
+    let merged = gln_model::GLN::merge(&models).unwrap();
+
+    let merged_prediction = merged.predict(&feature_vec);
+    assert_eq!(merged_prediction.context_index_map, models[0].predict(&feature_vec).context_index_map);
+    let merged_logit = merged.explain(&feature_vec).output_logit;
+    assert!(merged_logit > second_logit.min(first_logit));
+    assert!(merged_logit < second_logit.max(first_logit));
+    assert_eq!(merged.pseudo_count(&merged_prediction.context_index_map), 20.0);
+
+    let models = vec![build(11), build(12)];
+    assert_eq!(
+        gln_model::GLN::merge(&models).err(),
+        Some(MergeError::NeuronMismatch { layer_id: 0, neuron_id: 0 })
+    );
+    let models = vec![build(11), gln_model::GLN::new(vec![4, 1], 3, 3, 0.1, 5.0, 1.0, 0.0).with_seed(11)];
+    assert_eq!(
+        gln_model::GLN::check_merge_compatibility(&models),
+        Err(MergeError::LayerCountMismatch)
+    );
+}
+
+struct NeuronEventCounter {
+    counts: std::sync::Arc<std::sync::Mutex<Vec<usize>>>,
+}
+
+impl TrainObserver for NeuronEventCounter {
+    fn on_neuron_trained(&mut self, layer_id: usize, _neuron_id: usize, history: &NeuronTrainHistory) {
+        assert_eq!(history.weight_delta.len(), 3);
+        self.counts.lock().unwrap()[layer_id] += 1;
+    }
+}
+
+#[test]
+fn test_gln_train_observers() {
+    let counts = std::sync::Arc::new(std::sync::Mutex::new(vec![0, 0]));
+    let metrics_observer = MetricsObserver::new();
+    let metrics = metrics_observer.metrics();
+    let mut gln: gln_model::GLN = gln_model::GLN::new(vec![3, 1], 2, 3, 0.1, 5.0, 1.0, 0.0)
+        .with_observer(Box::new(NeuronEventCounter {
+            counts: counts.clone(),
+        }))
+        .with_observer(Box::new(metrics_observer));
+
+    let feature_vec = DVector::from_vec(vec![0.2, -0.4, 0.6]);
+    let mut loss_sum = 0.0;
+    for step in 0..10 {
+        let result = gln.predict_fit(&feature_vec, step % 2);
+        loss_sum += result.loss_histories[&1][&0];
+    }
+
+    assert_eq!(*counts.lock().unwrap(), vec![30, 10]);
+    let metrics = metrics.lock().unwrap();
+    assert_eq!(metrics.num_examples, 10);
+    assert!((metrics.mean_loss - loss_sum as f64 / 10.0).abs() < 1e-5);
+    assert_eq!(metrics.layer_mean_losses.len(), 2);
+}
+
+#[test]
+fn test_gln_predict_fit_with_workspace_matches_predict_fit() {
+    let mut gln: gln_model::GLN<f64> =
+        gln_model::GLN::new(vec![4, 3, 1], 3, 3, 0.1, 5.0, 1.0, 0.0).with_seed(5);
+    let mut workspace_gln = gln.clone();
+    let mut workspace = workspace_gln.workspace();
+
+    let examples = vec![
+        (vec![0.2, -0.4, 0.6], 1),
+        (vec![1.0, 0.3, -0.7], 0),
+        (vec![-0.5, 0.8, 0.1], 1),
+        (vec![0.2, -0.4, 0.6], 0),
+    ];
+    for _ in 0..5 {
+        for (features, target) in examples.iter() {
+            let feature_vec = DVector::from_vec(features.clone());
+            let expected = gln.predict_fit(&feature_vec, *target);
+            let actual =
+                workspace_gln.predict_fit_with_workspace(&feature_vec, *target, 1.0, &mut workspace);
+            assert!((expected.prediction - actual).abs() < 1e-12);
+            assert_eq!(
+                workspace.context_indices(),
+                &gln.predict(&feature_vec).context_index_map[..]
+            );
+        }
+    }
+}
+
+#[test]
+fn test_gln_shared_contexts() {
+    let build = || -> gln_model::GLN {
+        gln_model::GLN::new(vec![8, 4, 1], 3, 3, 0.1, 5.0, 1.0, 0.0)
+            .with_context_sharing(0, ContextSharing::SharedEvaluation)
+            .with_context_sharing(1, ContextSharing::Shared)
+            .with_seed(4)
+    };
+    let mut gln = build();
+    let feature_vec = DVector::from_vec(vec![0.2, -0.4, 0.6]);
+
+    let prediction = gln.predict(&feature_vec);
+    for layer_indices in prediction.context_index_map.iter() {
+        assert!(layer_indices.iter().all(|index| *index == layer_indices[0]));
+    }
+    assert_eq!(prediction.probability, build().predict(&feature_vec).probability);
+
+    for _ in 0..20 {
+        gln.predict_fit(&feature_vec, 1);
+    }
+    assert!(gln.predict(&feature_vec).probability > prediction.probability);
+}
+
+#[test]
+fn test_gln_with_fixed_contexts() {
+    let mut gln: gln_model::GLN<f64, FixedContext> =
+        gln_model::GLN::with_context_funcs(vec![4, 1], 2, 0.1, 5.0, 1.0, 0.0, |_, _| FixedContext)
+            .with_context_sharing(0, ContextSharing::SharedEvaluation);
+    let mut rng = ChaCha8Rng::seed_from_u64(3);
+    let mut examples = Vec::new();
+    for _ in 0..500 {
+        let features = DVector::from_vec(vec![rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)]);
+        let target = (features[0] > features[1]) as i32;
+        gln.predict_fit(&features, target);
+        examples.push((features, target));
+    }
+
+    let prediction = gln.predict(&examples[0].0);
+    assert!(prediction.context_index_map.iter().flatten().all(|index| *index == 0));
+    let predictions = examples.iter().map(|(features, _)| gln.predict(features).probability).collect();
+    let labels = examples.iter().map(|(_, target)| *target).collect();
+    assert!(accuracy(&predictions, &labels) > 0.95);
+}
+
+#[test]
+fn test_gln_with_categorical_and_half_space_contexts() {
+    // The label flips with the category, which the half-spaces of the numeric feature alone
+    // cannot see.
+    let mut rng = ChaCha8Rng::seed_from_u64(5);
+    let example = |rng: &mut ChaCha8Rng| {
+        let category = rng.gen_range(0..3) as f64;
+        let x: f64 = rng.gen_range(-1.0..1.0);
+        (DVector::from_vec(vec![category, x, 0.5]), ((x > 0.0) != (category == 1.0)) as i32)
+    };
+    let train: Vec<_> = (0..3000).map(|_| example(&mut rng)).collect();
+    let test: Vec<_> = (0..500).map(|_| example(&mut rng)).collect();
+    let labels = test.iter().map(|(_, target)| *target).collect();
+
+    let mut context_rng = ChaCha8Rng::seed_from_u64(7);
+    let mut gln: gln_model::GLN<f64, ConcatContext<CategoricalContext, HalfSpaceContext<f64>>> =
+        gln_model::GLN::with_context_funcs(vec![4, 1], 3, 0.1, 5.0, 1.0, 0.0, |_, _| {
+            let mut half_space = HalfSpaceContext::from_rng(4, 3, &mut context_rng);
+            half_space.mask_features(&[0]);
+            ConcatContext::new(CategoricalContext::new(vec![0], 3, 1), half_space)
+        });
+    let mut half_space_only: gln_model::GLN<f64> =
+        gln_model::GLN::new(vec![4, 1], 2, 3, 0.1, 5.0, 1.0, 0.0).with_seed(7);
+    for (features, target) in train.iter() {
+        gln.predict_fit(features, *target);
+        half_space_only.predict_fit(features, *target);
+    }
+
+    let test_accuracy = |predict: &dyn Fn(&DVector<f64>) -> f64| {
+        accuracy(&test.iter().map(|(features, _)| predict(features)).collect(), &labels)
+    };
+    let concat_accuracy = test_accuracy(&|features| gln.predict(features).probability);
+    assert!(concat_accuracy > 0.8);
+    assert!(concat_accuracy > test_accuracy(&|features| half_space_only.predict(features).probability) + 0.2);
+}
+
+#[test]
+fn test_gln_hyperplane_sampling_from_calibration_data() {
+    // Features far from the origin, where Gaussian biases put every example in the same context.
+    let examples: Vec<(DVector<f64>, i32)> = (0..200)
+        .map(|step| {
+            let x = 100.0 + (step % 20) as f64 / 10.0;
+            let y = 50.0 + (step % 7) as f64 / 7.0;
+            (DVector::from_vec(vec![x, y, 100.0]), (x > 101.0) as i32)
+        })
+        .collect();
+    let calibration_data: Vec<DVector<f64>> =
+        examples.iter().take(50).map(|(features, _)| features.clone()).collect();
+
+    for sampling in vec![
+        HyperplaneSampling::DataQuantileBias,
+        HyperplaneSampling::ThroughDataPoints,
+        HyperplaneSampling::AxisAligned,
+        HyperplaneSampling::Orthogonal,
+    ] {
+        let gln: gln_model::GLN<f64> = gln_model::GLN::new(vec![4, 1], 3, 3, 0.1, 5.0, 1.0, 0.0)
+            .with_hyperplane_sampling(sampling, &calibration_data, 9);
+        let distinct_contexts: std::collections::HashSet<Vec<Vec<usize>>> = examples
+            .iter()
+            .map(|(features, _)| gln.predict(features).context_index_map)
+            .collect();
+        assert!(distinct_contexts.len() > 1);
+    }
+
+    // Gaussian hyperplanes do not need calibration data.
+    let gln: gln_model::GLN<f64> =
+        gln_model::GLN::new(vec![4, 1], 3, 3, 0.1, 5.0, 1.0, 0.0).with_hyperplane_sampling(HyperplaneSampling::Gaussian, &[], 9);
+    assert!(gln.predict(&examples[0].0).probability > 0.0);
+}
+
+#[test]
+fn test_gln_context_adaptation_splits_shifted_data() {
+    let examples: Vec<(DVector<f64>, i32)> = (0..400)
+        .map(|step| {
+            let x = 100.0 + (step % 20) as f64 / 10.0;
+            (DVector::from_vec(vec![x, 50.0 + (step % 7) as f64 / 7.0, 100.0]), (x > 101.0) as i32)
+        })
+        .collect();
+    let count_contexts = |gln: &gln_model::GLN<f64>| {
+        examples
+            .iter()
+            .map(|(features, _)| gln.predict(features).context_index_map)
+            .collect::<std::collections::HashSet<Vec<Vec<usize>>>>()
+            .len()
+    };
+
+    for config in vec![
+        ContextAdaptationConfig::RedrawImbalanced {
+            max_imbalance: 0.95,
+            min_samples: 50,
+        },
+        ContextAdaptationConfig::RunningMedian {
+            learning_rate: 1.0,
+            interval: 50,
+            tolerance: 0.5,
+        },
+    ] {
+        let mut gln: gln_model::GLN<f64> = gln_model::GLN::new(vec![4, 1], 3, 3, 0.1, 5.0, 1.0, 0.0)
+            .with_seed(2)
+            .with_context_adaptation(config, 3);
+        assert_eq!(count_contexts(&gln), 1);
+        for (features, target) in examples.iter() {
+            gln.predict_fit(features, *target);
+        }
+        assert!(count_contexts(&gln) > 1);
+    }
+}
+
+#[test]
+fn test_gln_weight_initialization() {
+    let features = DVector::from_vec(vec![0.0, 0.25, 1.0]);
+    let identity: gln_model::GLN<f64> = gln_model::GLN::new(vec![4, 1], 2, 3, 0.1, 5.0, 1.0, 0.0)
+        .with_weight_initialization(0, WeightInitialization::Identity { input_index: 1 })
+        .with_weight_initialization(1, WeightInitialization::Identity { input_index: 0 });
+    assert!((identity.predict(&features).raw_probability - 0.25).abs() < 1e-9);
+
+    let zero: gln_model::GLN<f64> = gln_model::GLN::new(vec![4, 1], 2, 3, 0.1, 5.0, 1.0, 0.0)
+        .with_weight_initialization(1, WeightInitialization::Zero);
+    assert!((zero.predict(&features).raw_probability - 0.5).abs() < 1e-9);
+
+    let uniform = || -> gln_model::GLN<f64> {
+        gln_model::GLN::new(vec![4, 1], 2, 3, 0.1, 5.0, 1.0, 0.0)
+            .with_seed(1)
+            .with_weight_initialization(0, WeightInitialization::Uniform { low: -1.0, high: 1.0, seed: 7 })
+    };
+    assert_eq!(uniform().predict(&features).raw_probability, uniform().predict(&features).raw_probability);
+}
+
+#[test]
+fn test_gln_warm_start() {
+    let build = |context_dim: usize| -> gln_model::GLN<f64> {
+        gln_model::GLN::new(vec![4, 1], context_dim, 3, 0.1, 5.0, 1.0, 0.0).with_seed(3)
+    };
+    let examples: Vec<DVector<f64>> = (0..200)
+        .map(|step| DVector::from_vec(vec![(step % 5) as f64 / 5.0, (step % 7) as f64 / 7.0, (step % 3) as f64 / 3.0]))
+        .collect();
+    let mut source = build(2);
+    for features in examples.iter() {
+        source.predict_fit(features, 1);
+    }
+    let mean_probability = |gln: &gln_model::GLN<f64>| {
+        examples.iter().map(|features| gln.predict(features).raw_probability).sum::<f64>() / examples.len() as f64
+    };
+
+    // The models are drawn with different hyperplanes, which the warm start replaces by the
+    // source's, so every context of the larger model predicts like the source context it splits.
+    let larger = build(3).with_warm_start(&source);
+    for features in examples.iter() {
+        let prediction = larger.predict(features);
+        let source_prediction = source.predict(features);
+        assert!((prediction.raw_probability - source_prediction.raw_probability).abs() < 1e-12);
+        for (indices, source_indices) in prediction.context_index_map.iter().zip(&source_prediction.context_index_map) {
+            for (index, source_index) in indices.iter().zip(source_indices) {
+                assert_eq!(index & 0b11, *source_index);
+            }
+        }
+    }
+
+    let smaller = build(1).with_warm_start(&source);
+    assert!(mean_probability(&smaller) > mean_probability(&build(1)));
+}
+
+#[test]
+fn test_gln_weight_projection_per_layer() {
+    let mut gln: gln_model::GLN<f64> = gln_model::GLN::new(vec![4, 2, 1], 2, 3, 0.5, 5.0, 1.0, 0.0)
+        .with_seed(6)
+        .with_weight_projection(0, WeightProjection::NonNegative)
+        .with_weight_projection(1, WeightProjection::Simplex)
+        .with_weight_projection(2, WeightProjection::L2Ball);
+    for step in 0..300 {
+        let x = (step % 10) as f64 / 10.0;
+        let features = DVector::from_vec(vec![x, 1.0 - x, (step % 3) as f64]);
+        gln.predict_fit(&features, (x > 0.5) as i32);
+    }
+
+    let state = gln.to_state();
+    let layer_weights = |layer_id: usize| -> Vec<Vec<f64>> {
+        state.layers[layer_id]
+            .neurons
+            .iter()
+            .flat_map(|neuron| neuron.gate.weights.clone())
+            .collect()
+    };
+    assert!(layer_weights(0).iter().flatten().all(|weight| (0.0..=5.0).contains(weight)));
+    for weights in layer_weights(1) {
+        assert!(weights.iter().all(|weight| *weight >= 0.0));
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+    for weights in layer_weights(2) {
+        assert!(weights.iter().map(|weight| weight * weight).sum::<f64>().sqrt() <= 5.0 + 1e-9);
+    }
+    assert_eq!(state.layers[1].neurons[0].projection, WeightProjection::Simplex);
+}
+
+#[test]
+fn test_gln_monotonicity_property() {
+    let mut rng = ChaCha8Rng::seed_from_u64(12);
+    let monotonicity = vec![
+        Monotonicity::Increasing,
+        Monotonicity::Decreasing,
+        Monotonicity::Unconstrained,
+        Monotonicity::Unconstrained,
+    ];
+    // Contexts are resampled after the constraints are set, which must keep them masked.
+    let mut gln: gln_model::GLN<f64> = gln_model::GLN::new(vec![6, 3, 1], 3, 4, 0.2, 5.0, 1.0, 0.0)
+        .with_monotonicity(monotonicity)
+        .with_seed(5);
+
+    // The target moves against both constraints, so unconstrained training would break them.
+    let random_features = |rng: &mut ChaCha8Rng| {
+        DVector::from_vec(vec![rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0), rng.gen_range(-3.0..3.0), rng.gen_range(-3.0..3.0)])
+    };
+    for _ in 0..2000 {
+        let features = random_features(&mut rng);
+        let target = (features[1] - features[0] + 0.3 * features[2] > 0.0) as i32;
+        gln.predict_fit(&features, target);
+    }
+
+    for _ in 0..500 {
+        let features = random_features(&mut rng);
+        let probability = gln.predict(&features).probability;
+        let step = rng.gen_range(0.0..1.0);
+
+        let mut increased = features.clone();
+        increased[0] += step;
+        assert!(gln.predict(&increased).probability >= probability - 1e-12);
+
+        let mut decreased = features.clone();
+        decreased[1] += step;
+        assert!(gln.predict(&decreased).probability <= probability + 1e-12);
+    }
+}
+
+#[test]
+fn test_gln_train_diagnostics_detect_weight_saturation() {
+    let saturated_fraction = |weight_clipping_value: f32| {
+        let observer = DiagnosticsObserver::new();
+        let diagnostics = observer.diagnostics();
+        let mut gln: gln_model::GLN = gln_model::GLN::new(vec![4, 2, 1], 2, 3, 0.1, weight_clipping_value, 1.0, 0.0)
+            .with_seed(1)
+            .with_observer(Box::new(observer));
+        for step in 0..200 {
+            let x = (step % 10) as f32 / 10.0;
+            gln.predict_fit(&DVector::from_vec(vec![x, 1.0 - x, 0.5]), (x > 0.5) as i32);
+        }
+
+        let diagnostics = diagnostics.lock().unwrap();
+        assert_eq!(diagnostics.len(), 3);
+        for layer in diagnostics.iter() {
+            assert_eq!(layer.num_examples, 200);
+            assert!(layer.max_gradient_norm >= layer.mean_gradient_norm);
+            assert!(layer.mean_gradient_norm > 0.0);
+            assert!((0.0..=1.0).contains(&layer.clipped_prediction_fraction));
+        }
+        // The first feature of every example is 0 or 1 after normalization, so it is clipped.
+        assert!(diagnostics[0].clipped_input_fraction > 0.3);
+        diagnostics[0].saturated_weight_fraction
+    };
+
+    assert!(saturated_fraction(1e-3) > 0.9);
+    assert!(saturated_fraction(5.0) < 0.1);
+}
+
+#[test]
+fn test_gln_train_diagnostics_count_saturation_of_the_projection() {
+    let saturated_fraction = |projection: WeightProjection| {
+        let observer = DiagnosticsObserver::default();
+        let diagnostics = observer.diagnostics();
+        let mut gln: gln_model::GLN = gln_model::GLN::new(vec![4, 1], 2, 3, 0.1, 5.0, 1.0, 0.0)
+            .with_seed(1)
+            .with_weight_projection(0, projection)
+            .with_observer(Box::new(observer));
+        for step in 0..200 {
+            let x = (step % 10) as f32 / 10.0;
+            gln.predict_fit(&DVector::from_vec(vec![x, 1.0 - x, 0.5]), (x > 0.5) as i32);
+        }
+        let fraction = diagnostics.lock().unwrap()[0].saturated_weight_fraction;
+        fraction
+    };
+
+    // The weights stay far inside the hypercube, but the simplex pushes the useless ones to 0.
+    assert!(saturated_fraction(WeightProjection::Hypercube) < 0.1);
+    assert!(saturated_fraction(WeightProjection::Simplex) > 0.1);
+}
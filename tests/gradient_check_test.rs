@@ -0,0 +1,57 @@
+use gln::optimize::grad::{LogGeometricMixingGradient, OnlineGradient};
+use gln::optimize::gradient_check::GradientCheck;
+
+// A custom objective: the unregularized log loss plus a quartic penalty on the weights.
+struct QuarticPenaltyGradient {
+    log_loss: LogGeometricMixingGradient<f64>,
+    penalty: f64,
+}
+
+impl QuarticPenaltyGradient {
+    fn penalty_grad(&self, weights: &[f64], index: usize) -> f64 {
+        self.penalty * weights[index].powi(3)
+    }
+
+    fn penalty_loss(&self, weights: &[f64]) -> f64 {
+        self.penalty * weights.iter().map(|weight| weight.powi(4)).sum::<f64>() / 4.0
+    }
+}
+
+impl OnlineGradient<f64> for QuarticPenaltyGradient {
+    fn calculate_grad(&self, xs: &[f64], target: i32, weights: &[f64], index: usize, clipping_value: f64) -> f64 {
+        self.log_loss.calculate_grad(xs, target, weights, index, clipping_value) + self.penalty_grad(weights, index)
+    }
+
+    fn calculate_grad_by_logits(
+        &self,
+        input_logits: &[f64],
+        target: i32,
+        weights: &[f64],
+        index: usize,
+        sample_weight: f64,
+    ) -> f64 {
+        self.log_loss.calculate_grad_by_logits(input_logits, target, weights, index, sample_weight)
+            + self.penalty_grad(weights, index)
+    }
+}
+
+#[test]
+fn test_gradient_check_accepts_a_custom_gradient() {
+    let gradient = QuarticPenaltyGradient {
+        log_loss: LogGeometricMixingGradient::new(0.0, 1.0),
+        penalty: 0.3,
+    };
+    let check = GradientCheck::default();
+
+    check
+        .check(&gradient, |inputs, target, weights, clipping_value| {
+            gradient.log_loss.loss(inputs, target, weights, clipping_value) + gradient.penalty_loss(weights)
+        })
+        .unwrap();
+    check
+        .check_by_logits(&gradient, |input_logits, target, weights, sample_weight| {
+            gradient.log_loss.loss_by_logits(input_logits, target, weights, sample_weight)
+                + gradient.penalty_loss(weights)
+        })
+        .unwrap();
+}
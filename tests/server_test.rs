@@ -0,0 +1,203 @@
+#![cfg(feature = "server")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use gln::model::calibration::CalibrationConfig;
+use gln::model::gln_model::GLN;
+use gln::model::shared_model::SharedGLN;
+use gln::server::{serve, ServerState};
+use nalgebra::DVector;
+
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let body = response.split("\r\n\r\n").nth(1).unwrap().to_string();
+    (status, body)
+}
+
+#[test]
+fn test_save_and_load_round_trip() {
+    let path = std::env::temp_dir().join(format!("gln_round_trip_{}.json", std::process::id()));
+    let mut model: GLN = GLN::new(vec![4, 2, 1], 2, 3, 0.05, 5.0, 1.0, 0.0);
+    let features = DVector::from_vec(vec![0.3, -0.2, 0.7]);
+    for step in 0..50 {
+        model.predict_fit(&features, step % 2);
+    }
+
+    model.save(&path).unwrap();
+    let loaded: GLN = GLN::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        model.predict(&features).probability,
+        loaded.predict(&features).probability
+    );
+}
+
+#[test]
+fn test_save_and_load_keep_the_fitted_calibration() {
+    let path = std::env::temp_dir().join(format!("gln_calibration_round_trip_{}.json", std::process::id()));
+    let features = DVector::from_vec(vec![0.3, -0.2, 0.7]);
+    for calibration_config in [
+        CalibrationConfig::PlattScaling { learning_rate: 0.05 },
+        CalibrationConfig::Isotonic { num_bins: 10 },
+    ] {
+        let mut model: GLN = GLN::new(vec![4, 2, 1], 2, 3, 0.05, 5.0, 1.0, 0.0).with_calibration(calibration_config);
+        for step in 0..50 {
+            model.predict_fit(&features, (step % 3 == 0) as i32);
+        }
+        let prediction = model.predict(&features);
+        assert!(prediction.probability != prediction.raw_probability);
+
+        model.save(&path).unwrap();
+        let mut loaded: GLN = GLN::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(prediction.probability, loaded.predict(&features).probability);
+
+        // The loaded calibrator keeps fitting from where the saved one stopped.
+        model.predict_fit(&features, 1);
+        loaded.predict_fit(&features, 1);
+        assert_eq!(model.predict(&features).probability, loaded.predict(&features).probability);
+    }
+}
+
+#[test]
+fn test_server_predict_feedback_metrics_and_snapshot() {
+    let snapshot_path =
+        std::env::temp_dir().join(format!("gln_server_snapshot_{}.json", std::process::id()));
+    let model: GLN = GLN::new(vec![4, 2, 1], 2, 3, 0.05, 5.0, 1.0, 0.0);
+    let state = Arc::new(ServerState::new(
+        SharedGLN::new(model, 1),
+        snapshot_path.clone(),
+    ));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_state = Arc::clone(&state);
+    thread::spawn(move || serve(listener, server_state));
+
+    let (status, body) = request(addr, "POST", "/predict", r#"{"features":[0.1,0.2,0.3]}"#);
+    assert_eq!(status, 200);
+    assert!(body.contains("\"probability\""));
+
+    for step in 0..10 {
+        let feedback = format!(r#"{{"features":[0.1,0.2,0.3],"target":{}}}"#, step % 2);
+        let (status, _) = request(addr, "POST", "/feedback", &feedback);
+        assert_eq!(status, 200);
+    }
+    let (status, _) = request(
+        addr,
+        "POST",
+        "/feedback",
+        r#"{"features":[0.1,0.2,0.3],"target":1,"weight":2.0}"#,
+    );
+    assert_eq!(status, 200);
+
+    let (status, body) = request(addr, "GET", "/metrics", "");
+    assert_eq!(status, 200);
+    assert!(body.contains("\"num_feedbacks\":11"));
+    assert_eq!(state.metrics().num_predictions, 1);
+
+    let (status, _) = request(addr, "POST", "/snapshot", "");
+    assert_eq!(status, 200);
+    let saved: GLN = GLN::load(&snapshot_path).unwrap();
+    std::fs::remove_file(&snapshot_path).unwrap();
+    let (_, body) = request(addr, "POST", "/predict", r#"{"features":[0.1,0.2,0.3]}"#);
+    let served: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let features = DVector::from_vec(vec![0.1, 0.2, 0.3]);
+    assert_eq!(
+        saved.predict(&features).probability,
+        served["probability"].as_f64().unwrap() as f32
+    );
+
+    let (status, _) = request(addr, "POST", "/predict", "not json");
+    assert_eq!(status, 400);
+    let (status, _) = request(addr, "POST", "/feedback", r#"{"features":[0.1],"target":3}"#);
+    assert_eq!(status, 400);
+    let (status, _) = request(addr, "GET", "/unknown", "");
+    assert_eq!(status, 404);
+}
+
+fn unused_snapshot_path() -> PathBuf {
+    std::env::temp_dir().join(format!("gln_server_unused_{}.json", std::process::id()))
+}
+
+fn start_server(snapshot_path: PathBuf) -> (SocketAddr, Arc<ServerState>) {
+    let model: GLN = GLN::new(vec![4, 2, 1], 2, 3, 0.05, 5.0, 1.0, 0.0);
+    let state = Arc::new(ServerState::new(SharedGLN::new(model, 1), snapshot_path));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_state = Arc::clone(&state);
+    thread::spawn(move || serve(listener, server_state));
+    (addr, state)
+}
+
+#[test]
+fn test_server_rejects_wrong_feature_lengths_and_keeps_training() {
+    let (addr, state) = start_server(unused_snapshot_path());
+
+    for features in ["[0.1]", "[]", "[0.1,0.2,0.3,0.4]"] {
+        let (status, body) = request(addr, "POST", "/predict", &format!(r#"{{"features":{}}}"#, features));
+        assert_eq!(status, 400);
+        assert!(body.contains("features"));
+        let feedback = format!(r#"{{"features":{},"target":1}}"#, features);
+        let (status, _) = request(addr, "POST", "/feedback", &feedback);
+        assert_eq!(status, 400);
+    }
+
+    let (status, _) = request(addr, "POST", "/feedback", r#"{"features":[0.1,0.2,0.3],"target":1}"#);
+    assert_eq!(status, 200);
+    assert_eq!(state.metrics().num_feedbacks, 1);
+    assert_eq!(state.metrics().num_predictions, 0);
+}
+
+#[test]
+fn test_server_rejects_oversized_bodies() {
+    let (addr, _) = start_server(unused_snapshot_path());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "POST /predict HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1000000000000\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large"));
+
+    let (status, _) = request(addr, "POST", "/predict", r#"{"features":[0.1,0.2,0.3]}"#);
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn test_server_concurrent_snapshots_leave_a_complete_model() {
+    let snapshot_dir = std::env::temp_dir().join(format!("gln_concurrent_snapshots_{}", std::process::id()));
+    std::fs::create_dir_all(&snapshot_dir).unwrap();
+    let snapshot_path = snapshot_dir.join("model.json");
+    let (addr, _) = start_server(snapshot_path.clone());
+
+    let requests: Vec<_> = (0..8)
+        .map(|_| thread::spawn(move || request(addr, "POST", "/snapshot", "").0))
+        .collect();
+    for handle in requests {
+        assert_eq!(handle.join().unwrap(), 200);
+    }
+
+    assert!(GLN::<f32>::load(&snapshot_path).is_ok());
+    // Every temporary file was renamed into place.
+    assert_eq!(std::fs::read_dir(&snapshot_dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&snapshot_dir).unwrap();
+}
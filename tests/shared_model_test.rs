@@ -0,0 +1,87 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+
+use gln::model::gln_model::GLN;
+use gln::model::observer::{ExampleTrainEvent, TrainObserver};
+use gln::model::shared_model::SharedGLN;
+use nalgebra::DVector;
+
+#[test]
+fn test_shared_gln_concurrent_predict_and_train() {
+    let publish_interval = 10;
+    let num_updates = 2000;
+    let model: GLN = GLN::new(vec![8, 4, 1], 3, 3, 0.05, 5.0, 1.0, 0.0);
+    let shared = Arc::new(SharedGLN::new(model, publish_interval));
+
+    let readers: Vec<_> = (0..4)
+        .map(|reader_id| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let features = DVector::from_vec(vec![0.1 * reader_id as f32, 0.5, 0.3]);
+                let mut last_num_updates = 0;
+                loop {
+                    let snapshot = shared.snapshot();
+                    assert!(snapshot.num_updates >= last_num_updates);
+                    assert_eq!(snapshot.num_updates % publish_interval, 0);
+                    last_num_updates = snapshot.num_updates;
+
+                    let probability = snapshot.model.predict(&features).probability;
+                    assert!((0.0..=1.0).contains(&probability));
+                    if last_num_updates == num_updates {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let trainers: Vec<_> = (0..2)
+        .map(|trainer_id| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                for step in 0..num_updates / 2 {
+                    let features = DVector::from_vec(vec![0.3, 0.1 * (step % 10) as f32, 0.2]);
+                    shared.predict_fit(&features, (trainer_id + step) as i32 % 2);
+                }
+            })
+        })
+        .collect();
+
+    for handle in trainers.into_iter().chain(readers) {
+        handle.join().unwrap();
+    }
+    assert_eq!(shared.snapshot().num_updates, num_updates);
+}
+
+struct PanicOnceObserver {
+    panicked: bool,
+}
+
+impl TrainObserver for PanicOnceObserver {
+    fn on_example_trained(&mut self, _event: &ExampleTrainEvent) {
+        if !self.panicked {
+            self.panicked = true;
+            panic!("observer failure");
+        }
+    }
+}
+
+#[test]
+fn test_shared_gln_keeps_training_after_a_failed_update() {
+    let model: GLN = GLN::new(vec![4, 1], 2, 3, 0.05, 5.0, 1.0, 0.0)
+        .with_observer(Box::new(PanicOnceObserver { panicked: false }));
+    let shared = SharedGLN::new(model, 1);
+    let features = DVector::from_vec(vec![0.3, 0.1, 0.2]);
+
+    let wrong_length = DVector::from_vec(vec![0.3]);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| shared.predict_fit(&wrong_length, 1)));
+    assert!(result.is_err());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| shared.predict_fit(&features, 1)));
+    assert!(result.is_err());
+
+    shared.predict_fit(&features, 1);
+    shared.predict_fit(&features, 0);
+    assert_eq!(shared.snapshot().num_updates, 2);
+}